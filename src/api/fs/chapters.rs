@@ -0,0 +1,291 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    time::Duration,
+};
+
+use id3::TagLike;
+
+use crate::api::Chapter;
+
+// Chapter tracks have one sample per chapter, tables claiming more are broken
+const MAX_CHAPTERS: usize = 10000;
+
+// Reads the chapter marks of a file. Files without chapters return an empty list.
+pub fn read_chapters(path: &Path, extension: &str) -> Vec<Chapter> {
+    let mut chapters = match extension {
+        "mp3" => read_id3_chapters(path),
+        "m4a" | "m4b" => read_mp4_chapters(path),
+        _ => None,
+    }
+    .unwrap_or_default();
+    chapters.sort_by_key(|f| f.start);
+    chapters
+}
+
+// Chapters stored as CHAP frames of an ID3v2 tag
+fn read_id3_chapters(path: &Path) -> Option<Vec<Chapter>> {
+    let tag = id3::Tag::read_from_path(path).ok()?;
+    Some(
+        tag.chapters()
+            .enumerate()
+            .map(|(i, f)| Chapter {
+                title: chapter_title(f.title(), i),
+                start: Duration::from_millis(f.start_time as u64),
+            })
+            .collect(),
+    )
+}
+
+// Chapters of MP4 files are either stored in a Nero chapter list (moov/udta/chpl) or in a
+// QuickTime text track which is referenced by the audio track.
+fn read_mp4_chapters(path: &Path) -> Option<Vec<Chapter>> {
+    let mut file = File::open(path).ok()?;
+    let moov = read_top_level_box(&mut file, b"moov")?;
+
+    if let Some(chpl) = find_box(&moov, &[b"udta", b"chpl"]) {
+        let chapters = parse_chpl(chpl)?;
+        if !chapters.is_empty() {
+            return Some(chapters);
+        }
+    }
+
+    let tracks = child_boxes(&moov)
+        .filter(|f| &f.0 == b"trak")
+        .map(|f| f.1)
+        .collect::<Vec<&[u8]>>();
+    let chapter_track_id = tracks
+        .iter()
+        .find_map(|f| find_box(f, &[b"tref", b"chap"]))
+        .and_then(|f| read_u32(f, 0))?;
+    let chapter_track = tracks.into_iter().find(|f| {
+        find_box(f, &[b"tkhd"]).and_then(track_id) == Some(chapter_track_id)
+    })?;
+    parse_text_track(&mut file, chapter_track)
+}
+
+fn parse_chpl(chpl: &[u8]) -> Option<Vec<Chapter>> {
+    // Version 1 has four reserved bytes after the flags
+    let mut pos = if chpl.first()? == &1 { 8 } else { 4 };
+    let count = *chpl.get(pos)? as usize;
+    pos += 1;
+    let mut chapters = vec![];
+    for i in 0..count {
+        // The start is stored in 100 nanosecond units
+        let start = read_u64(chpl, pos)?;
+        let length = *chpl.get(pos + 8)? as usize;
+        let title = chpl.get(pos + 9..pos + 9 + length)?;
+        pos += 9 + length;
+        chapters.push(Chapter {
+            title: chapter_title(Some(&String::from_utf8_lossy(title)), i),
+            start: Duration::from_nanos(start.saturating_mul(100)),
+        });
+    }
+    Some(chapters)
+}
+
+fn parse_text_track(file: &mut File, trak: &[u8]) -> Option<Vec<Chapter>> {
+    let mdhd = find_box(trak, &[b"mdia", b"mdhd"])?;
+    let timescale = if mdhd.first()? == &1 {
+        read_u32(mdhd, 20)?
+    } else {
+        read_u32(mdhd, 12)?
+    };
+    if timescale == 0 {
+        return None;
+    }
+    let stbl = find_box(trak, &[b"mdia", b"minf", b"stbl"])?;
+
+    // Size of every sample
+    let stsz = find_box(stbl, &[b"stsz"])?;
+    let sample_size = read_u32(stsz, 4)?;
+    let sample_count = read_u32(stsz, 8)? as usize;
+    if sample_count > MAX_CHAPTERS {
+        return None;
+    }
+    let sizes = (0..sample_count)
+        .map(|i| {
+            if sample_size != 0 {
+                Some(sample_size)
+            } else {
+                read_u32(stsz, 12 + i * 4)
+            }
+        })
+        .collect::<Option<Vec<u32>>>()?;
+
+    // Start time of every sample, the counts can't add up to more than there are samples
+    let stts = find_box(stbl, &[b"stts"])?;
+    let mut starts = vec![];
+    let mut time = 0u64;
+    for i in 0..read_u32(stts, 4)? as usize {
+        let count = read_u32(stts, 8 + i * 8)? as usize;
+        let delta = read_u32(stts, 12 + i * 8)? as u64;
+        for _ in 0..count.min(sample_count - starts.len()) {
+            starts.push(time);
+            time += delta;
+        }
+        if starts.len() == sample_count {
+            break;
+        }
+    }
+
+    // File offset of every chunk
+    let chunk_offsets = if let Some(stco) = find_box(stbl, &[b"stco"]) {
+        (0..read_u32(stco, 4)? as usize)
+            .map(|i| read_u32(stco, 8 + i * 4).map(|f| f as u64))
+            .collect::<Option<Vec<u64>>>()?
+    } else {
+        let co64 = find_box(stbl, &[b"co64"])?;
+        (0..read_u32(co64, 4)? as usize)
+            .map(|i| read_u64(co64, 8 + i * 8))
+            .collect::<Option<Vec<u64>>>()?
+    };
+
+    // Maps the samples onto the chunks
+    let stsc = find_box(stbl, &[b"stsc"])?;
+    let stsc_entries = (0..read_u32(stsc, 4)? as usize)
+        .map(|i| Some((read_u32(stsc, 8 + i * 12)?, read_u32(stsc, 12 + i * 12)?)))
+        .collect::<Option<Vec<(u32, u32)>>>()?;
+    let mut offsets = vec![];
+    for (chunk, chunk_offset) in chunk_offsets.iter().enumerate() {
+        let samples_per_chunk = stsc_entries
+            .iter()
+            .take_while(|f| f.0 as usize <= chunk + 1)
+            .last()?
+            .1;
+        let mut offset = *chunk_offset;
+        for _ in 0..samples_per_chunk {
+            let size = *sizes.get(offsets.len())?;
+            offsets.push((offset, size));
+            offset = offset.checked_add(size as u64)?;
+        }
+    }
+
+    let file_length = file.metadata().ok()?.len();
+    let mut chapters = vec![];
+    for (i, (offset, size)) in offsets.into_iter().enumerate() {
+        // Samples have to be inside of the file
+        if offset.checked_add(size as u64)? > file_length {
+            return None;
+        }
+        let mut sample = vec![0; size as usize];
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut sample).ok()?;
+        // Text samples start with the length of the text
+        let length = read_u16(&sample, 0).unwrap_or(0) as usize;
+        let title = decode_text(sample.get(2..2 + length).unwrap_or(&[]));
+        chapters.push(Chapter {
+            title: chapter_title(Some(&title), i),
+            start: Duration::from_millis(starts.get(i)?.saturating_mul(1000) / timescale as u64),
+        });
+    }
+    Some(chapters)
+}
+
+fn track_id(tkhd: &[u8]) -> Option<u32> {
+    if tkhd.first()? == &1 {
+        read_u32(tkhd, 20)
+    } else {
+        read_u32(tkhd, 12)
+    }
+}
+
+// Text samples are either UTF-8 or UTF-16 with a byte order mark
+fn decode_text(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        let units = bytes[2..]
+            .chunks_exact(2)
+            .map(|f| u16::from_be_bytes([f[0], f[1]]))
+            .collect::<Vec<u16>>();
+        String::from_utf16_lossy(&units)
+    } else if bytes.starts_with(&[0xFF, 0xFE]) {
+        let units = bytes[2..]
+            .chunks_exact(2)
+            .map(|f| u16::from_le_bytes([f[0], f[1]]))
+            .collect::<Vec<u16>>();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(bytes).to_string()
+    }
+}
+
+fn chapter_title(title: Option<&str>, index: usize) -> String {
+    match title.map(|f| f.trim()) {
+        Some(t) if !t.is_empty() => t.to_string(),
+        _ => format!("Chapter {}", index + 1),
+    }
+}
+
+// Searches the top level boxes of the file and reads the payload of the first matching one
+pub(super) fn read_top_level_box(file: &mut File, name: &[u8; 4]) -> Option<Vec<u8>> {
+    let file_length = file.metadata().ok()?.len();
+    let mut pos = 0;
+    while pos + 8 <= file_length {
+        file.seek(SeekFrom::Start(pos)).ok()?;
+        let mut header = [0; 16];
+        file.read_exact(&mut header[..8]).ok()?;
+        let (size, header_length) = match read_u32(&header, 0)? {
+            0 => (file_length - pos, 8),
+            1 => {
+                file.read_exact(&mut header[8..]).ok()?;
+                (read_u64(&header, 8)?, 16)
+            }
+            s => (s as u64, 8),
+        };
+        // Boxes have to fit into the rest of the file
+        if size < header_length || size > file_length - pos {
+            return None;
+        }
+        if &header[4..8] == name {
+            let mut payload = vec![0; (size - header_length) as usize];
+            file.read_exact(&mut payload).ok()?;
+            return Some(payload);
+        }
+        pos += size;
+    }
+    None
+}
+
+// Follows the given path of box names starting at the children of `data`
+pub(super) fn find_box<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    let (name, rest) = path.split_first()?;
+    let child = child_boxes(data).find(|f| &&f.0 == name)?.1;
+    if rest.is_empty() {
+        Some(child)
+    } else {
+        find_box(child, rest)
+    }
+}
+
+fn child_boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        let size = read_u32(data, pos)? as usize;
+        let name: [u8; 4] = data.get(pos + 4..pos + 8)?.try_into().ok()?;
+        let (size, header_length) = match size {
+            0 => (data.len() - pos, 8),
+            1 => (usize::try_from(read_u64(data, pos + 8)?).ok()?, 16),
+            s => (s, 8),
+        };
+        if size < header_length {
+            return None;
+        }
+        let end = pos.checked_add(size)?;
+        let payload = data.get(pos + header_length..end)?;
+        pos = end;
+        Some((name, payload))
+    })
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+pub(super) fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+pub(super) fn read_u64(data: &[u8], pos: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(pos..pos + 8)?.try_into().ok()?))
+}
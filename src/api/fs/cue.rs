@@ -0,0 +1,208 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use itertools::Itertools;
+
+use super::{duration, FsSong};
+
+// A track of a cue sheet, which points into a single audio file
+struct CueTrack {
+    file: PathBuf,
+    number: u16,
+    title: Option<String>,
+    performer: Option<String>,
+    start: Option<Duration>,
+}
+
+// Creates a song for every track of a cue sheet. The songs reference the audio file of the
+// sheet and are played from their start to the start of the next track of the same file.
+pub fn read_cue_sheet(path: &Path) -> Vec<FsSong> {
+    let contents = match fs::read(path) {
+        Ok(c) => String::from_utf8_lossy(&c).to_string(),
+        Err(_) => return vec![],
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut album = None;
+    let mut album_performer = None;
+    let mut release_date = None;
+    let mut file = None;
+    let mut tracks: Vec<CueTrack> = vec![];
+    for line in contents.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        match command.to_uppercase().as_str() {
+            "FILE" => file = Some(resolve_file(dir, &unquote(rsplit_type(args)))),
+            "TRACK" => {
+                if let Some(file) = &file {
+                    tracks.push(CueTrack {
+                        file: file.to_owned(),
+                        number: args
+                            .split_whitespace()
+                            .next()
+                            .and_then(|f| f.parse().ok())
+                            .unwrap_or(0),
+                        title: None,
+                        performer: None,
+                        start: None,
+                    });
+                }
+            }
+            "TITLE" => match tracks.last_mut() {
+                Some(track) => track.title = Some(unquote(args)),
+                None => album = Some(unquote(args)),
+            },
+            "PERFORMER" => match tracks.last_mut() {
+                Some(track) => track.performer = Some(unquote(args)),
+                None => album_performer = Some(unquote(args)),
+            },
+            "INDEX" => {
+                let mut parts = args.split_whitespace();
+                if parts.next() == Some("01") {
+                    if let Some(track) = tracks.last_mut() {
+                        track.start = parts.next().and_then(parse_time);
+                    }
+                }
+            }
+            "REM" => {
+                if let Some(("DATE", date)) = args.split_once(' ') {
+                    release_date = Some(unquote(date));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let file_lengths = tracks
+        .iter()
+        .map(|f| f.file.to_owned())
+        .unique()
+        .map(|f| (file_length(&f), f))
+        .collect::<Vec<(Option<Duration>, PathBuf)>>();
+
+    tracks
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| f.start.is_some() && f.file.is_file())
+        .map(|(i, f)| {
+            let start = f.start.unwrap();
+            // Tracks end where the next track of the same file starts
+            let end = tracks
+                .get(i + 1)
+                .filter(|n| n.file == f.file)
+                .and_then(|n| n.start);
+            let file_length = file_lengths
+                .iter()
+                .find(|l| l.1 == f.file)
+                .and_then(|l| l.0);
+            let length = end
+                .or(file_length)
+                .and_then(|e| e.checked_sub(start))
+                .unwrap_or_default();
+            FsSong::fastnew(
+                f.file.to_owned(),
+                f.title
+                    .to_owned()
+                    .unwrap_or(format!("Track {}", f.number)),
+                length.as_millis() as f64,
+                f.number,
+                album.to_owned().unwrap_or_default(),
+                release_date.to_owned().unwrap_or("0".to_string()),
+                vec![],
+            )
+            .with_offset(start, end)
+            .with_artists(f.performer.to_owned(), album_performer.to_owned())
+        })
+        .collect()
+}
+
+// The cue sheets next to an audio file that reference it
+pub fn find_cue_sheets(file: &Path) -> Vec<PathBuf> {
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    entries
+        .filter_map(|f| f.ok().map(|f| f.path()))
+        .filter(|f| f.extension().is_some_and(|e| e.eq_ignore_ascii_case("cue")))
+        .filter(|f| read_cue_sheet(f).iter().any(|song| song.path == file))
+        .sorted()
+        .collect()
+}
+
+// Cue sheets often still reference the image before it got converted to another format, so
+// a file with the same name but another extension is used if the referenced one is missing
+fn resolve_file(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    if path.is_file() {
+        return path;
+    }
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|f| f.ok().map(|f| f.path()))
+        .find(|f| {
+            f.file_stem() == path.file_stem()
+                && !f.extension().is_some_and(|e| e.eq_ignore_ascii_case("cue"))
+        })
+        .unwrap_or(path)
+}
+
+fn file_length(path: &Path) -> Option<Duration> {
+    duration::read_duration(path, FsSong::audio_extension(path)?)
+}
+
+// Removes the file type from the arguments of a FILE command
+fn rsplit_type(args: &str) -> &str {
+    match args.trim().rsplit_once(' ') {
+        Some((file, _)) => file,
+        None => args,
+    }
+}
+
+fn unquote(s: &str) -> String {
+    s.trim().trim_matches('"').to_string()
+}
+
+// Cue sheets store times as minutes:seconds:frames with 75 frames per second
+fn parse_time(time: &str) -> Option<Duration> {
+    let parts = time
+        .split(':')
+        .map(|f| f.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    match parts[..] {
+        [minutes, seconds, frames] if seconds < 60 && frames < 75 => Some(Duration::from_millis(
+            minutes.checked_mul(60_000)? + seconds * 1000 + frames * 1000 / 75,
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_time("00:00:00"), Some(Duration::ZERO));
+        assert_eq!(parse_time("03:25:45"), Some(Duration::from_millis(205_600)));
+        // Minutes aren't limited, long images go beyond an hour
+        assert_eq!(parse_time("75:00:00"), Some(Duration::from_secs(4500)));
+    }
+
+    #[test]
+    fn malformed_times() {
+        assert_eq!(parse_time(""), None);
+        assert_eq!(parse_time("03:25"), None);
+        assert_eq!(parse_time("00:03:25:45"), None);
+        assert_eq!(parse_time("aa:bb:cc"), None);
+        assert_eq!(parse_time("-1:00:00"), None);
+        assert_eq!(parse_time("00:60:00"), None);
+        assert_eq!(parse_time("00:00:75"), None);
+        assert_eq!(parse_time("18446744073709551615:00:00"), None);
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use itertools::Itertools;
+
+use super::fingerprint::{self, Fingerprints};
+use crate::api::{Album, Song};
+
+// Songs whose lengths differ by at most this many seconds can be the same recording
+const LENGTH_TOLERANCE: usize = 3;
+
+// A song that is likely in the library several times, e.g. in different formats
+#[derive(Clone)]
+pub struct Duplicate {
+    // Songs with the same group are duplicates of each other
+    pub group: usize,
+    pub song: Box<dyn Song + Send + Sync>,
+    // Extension of the file in upper case, e.g. FLAC
+    pub format: String,
+    // Average bitrate in kbit/s, calculated from the size and length of the file
+    pub bitrate: Option<u64>,
+    // Whether the audio matches another song of the group by its fingerprint
+    pub identical: bool,
+}
+
+// Progress of comparing the songs of the library, by the number of songs
+#[derive(Clone, Default)]
+pub struct DuplicateProgress {
+    pub total: usize,
+    pub processed: usize,
+}
+
+// Finding duplicates compares the fingerprints of many songs, so it runs in the background
+#[derive(Clone)]
+pub struct DuplicateSearch {
+    progress: Arc<Mutex<DuplicateProgress>>,
+    result: Arc<Mutex<Option<Vec<Duplicate>>>>,
+}
+
+impl DuplicateSearch {
+    pub fn start(
+        albums: Vec<Box<dyn Album + Send + Sync>>,
+        fingerprints: Option<Fingerprints>,
+    ) -> DuplicateSearch {
+        let search = DuplicateSearch {
+            progress: Arc::new(Mutex::new(DuplicateProgress::default())),
+            result: Arc::new(Mutex::new(None)),
+        };
+        let (progress, result) = (search.progress.clone(), search.result.clone());
+        thread::spawn(move || {
+            let duplicates = find(&albums, fingerprints.as_ref(), &progress);
+            *result.lock().unwrap() = Some(duplicates);
+        });
+        search
+    }
+    pub fn get_progress(&self) -> DuplicateProgress {
+        self.progress.lock().unwrap().clone()
+    }
+    // The duplicates once every song was compared
+    pub fn get_result(&self) -> Option<Vec<Duplicate>> {
+        self.result.lock().unwrap().clone()
+    }
+}
+
+// Groups the songs with the same artist and title or the same audio, if they are about the same
+// length. Within a group the song with the highest bitrate comes first.
+fn find(
+    albums: &[Box<dyn Album + Send + Sync>],
+    fingerprints: Option<&Fingerprints>,
+    progress: &Mutex<DuplicateProgress>,
+) -> Vec<Duplicate> {
+    // Songs of about the same length are next to each other
+    let songs = albums
+        .iter()
+        .flat_map(|album| album.get_songs())
+        // Tracks of cue sheets share their file, so a single one can't be removed
+        .filter(|song| song.get_start().is_none() && song.get_filepath().is_some())
+        .filter_map(|song| Some((song.get_length_secs()?, song)))
+        .sorted_by_key(|f| f.0)
+        .collect_vec();
+    let keys = songs
+        .iter()
+        .map(|(_, song)| (normalize(&song.get_artist_name()), normalize(&song.get_title())))
+        .collect_vec();
+    // Files that couldn't be decoded have an empty fingerprint
+    let prints = songs
+        .iter()
+        .map(|(_, song)| {
+            fingerprints
+                .and_then(|f| f.get(&song.get_filepath()?))
+                .filter(|f| !f.is_empty())
+        })
+        .collect_vec();
+    progress.lock().unwrap().total = songs.len();
+
+    let mut groups = (0..songs.len()).collect_vec();
+    let mut identical = vec![false; songs.len()];
+    for i in 0..songs.len() {
+        for j in i + 1..songs.len() {
+            if songs[j].0 - songs[i].0 > LENGTH_TOLERANCE {
+                break;
+            }
+            // Titles without letters or digits can't be compared
+            if !keys[i].1.is_empty() && keys[i] == keys[j] {
+                join(&mut groups, i, j);
+            }
+            if let (Some(a), Some(b)) = (&prints[i], &prints[j]) {
+                if fingerprint::is_match(a, b) {
+                    join(&mut groups, i, j);
+                    (identical[i], identical[j]) = (true, true);
+                }
+            }
+        }
+        progress.lock().unwrap().processed = i + 1;
+    }
+    let mut songs = songs.into_iter().map(|f| Some(f.1)).collect_vec();
+    (0..songs.len())
+        .into_group_map_by(|i| root(&groups, *i))
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .sorted_by(|a, b| keys[a[0]].cmp(&keys[b[0]]))
+        .enumerate()
+        .flat_map(|(group, members)| {
+            members
+                .into_iter()
+                .filter_map(|i| Some(Duplicate::new(group, songs[i].take()?, identical[i])))
+                .sorted_by(|a, b| b.bitrate.cmp(&a.bitrate))
+                .collect_vec()
+        })
+        .collect()
+}
+
+// The songs of the group of a duplicate, without the duplicate itself
+pub fn others(duplicates: &[Duplicate], keep: usize) -> Vec<Box<dyn Song + Send + Sync>> {
+    let group = duplicates[keep].group;
+    duplicates
+        .iter()
+        .enumerate()
+        .filter(|(i, f)| f.group == group && *i != keep)
+        .map(|(_, f)| f.song.to_owned())
+        .collect()
+}
+
+impl Duplicate {
+    fn new(group: usize, song: Box<dyn Song + Send + Sync>, identical: bool) -> Duplicate {
+        let path = song.get_filepath().unwrap_or_default();
+        let format = path
+            .extension()
+            .map(|f| f.to_string_lossy().to_uppercase())
+            .unwrap_or_default();
+        let bitrate = match (fs::metadata(&path), song.get_length_secs()) {
+            (Ok(metadata), Some(secs)) if secs > 0 => Some(metadata.len() * 8 / secs as u64 / 1000),
+            _ => None,
+        };
+        Duplicate {
+            group,
+            song,
+            format,
+            bitrate,
+            identical,
+        }
+    }
+}
+
+// The first song of the group of a song, groups point to a song of the same group
+fn root(groups: &[usize], mut i: usize) -> usize {
+    while groups[i] != i {
+        i = groups[i];
+    }
+    i
+}
+
+fn join(groups: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (root(groups, a), root(groups, b));
+    groups[a.max(b)] = a.min(b);
+}
+
+// Ignores case, punctuation and spacing, which often differ between sources
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
@@ -0,0 +1,273 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    time::Duration,
+};
+
+use rodio::{Decoder, Source};
+
+use super::{
+    chapters::{find_box, read_top_level_box, read_u32, read_u64},
+    formats::read_ogg_packets,
+};
+
+// Reads the duration of an audio file from the headers of its container. Files without usable
+// headers are decoded instead, which is slower.
+pub fn read_duration(path: &Path, extension: &str) -> Option<Duration> {
+    match extension {
+        "mp3" => read_mp3_duration(path).or_else(|| mp3_duration::from_path(path).ok()),
+        "flac" => read_flac_duration(path),
+        "wav" => read_wav_duration(path),
+        "aiff" => read_aiff_duration(path),
+        "ogg" | "opus" => read_ogg_duration(path),
+        "m4a" | "m4b" => read_mp4_duration(path),
+        _ => None,
+    }
+    .or_else(|| decode_duration(path))
+}
+
+// VBR files start with a Xing or VBRI frame containing the number of frames. Files without one
+// are constant bitrate and measured by mp3_duration.
+fn read_mp3_duration(path: &Path) -> Option<Duration> {
+    let mut file = File::open(path).ok()?;
+    let mut id3_header = [0; 10];
+    file.read_exact(&mut id3_header).ok()?;
+    // Skip the ID3v2 tag in front of the first frame
+    let offset = if &id3_header[..3] == b"ID3" {
+        let size = id3_header[6..10]
+            .iter()
+            .fold(0, |size, f| size << 7 | (*f & 0x7f) as u64);
+        let footer = if id3_header[5] & 0x10 != 0 { 10 } else { 0 };
+        10 + size + footer
+    } else {
+        0
+    };
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let mut data = vec![];
+    file.take(16384).read_to_end(&mut data).ok()?;
+    read_vbr_header(&data)
+}
+
+// Reads the Xing or VBRI frame at the start of the audio data
+fn read_vbr_header(data: &[u8]) -> Option<Duration> {
+    let start = data
+        .windows(2)
+        .position(|f| f[0] == 0xff && f[1] & 0xe0 == 0xe0)?;
+    let frame = data.get(start..)?;
+    // 3 is MPEG 1, 2 is MPEG 2 and 0 is MPEG 2.5
+    let version = (frame[1] >> 3) & 3;
+    // 1 is layer III, 2 is layer II and 3 is layer I
+    let layer = (frame[1] >> 1) & 3;
+    let sample_rate = match version {
+        3 => [44100, 48000, 32000],
+        2 => [22050, 24000, 16000],
+        0 => [11025, 12000, 8000],
+        _ => return None,
+    }
+    .get(((*frame.get(2)? >> 2) & 3) as usize)?
+    .to_owned();
+    let samples_per_frame = match (layer, version) {
+        (3, _) => 384,
+        (2, _) | (1, 3) => 1152,
+        (1, _) => 576,
+        _ => return None,
+    };
+    let mono = frame.get(3)? >> 6 == 3;
+    let side_info = match (version == 3, mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+
+    let xing = frame.get(4 + side_info..)?;
+    let frames = if xing.starts_with(b"Xing") || xing.starts_with(b"Info") {
+        let flags = read_u32(xing, 4)?;
+        if flags & 1 == 0 {
+            return None;
+        }
+        read_u32(xing, 8)?
+    } else if frame.get(36..40)? == b"VBRI" {
+        read_u32(frame, 36 + 14)?
+    } else {
+        return None;
+    };
+    Some(Duration::from_secs_f64(
+        frames as f64 * samples_per_frame as f64 / sample_rate as f64,
+    ))
+}
+
+// The STREAMINFO block at the start of the file stores the sample rate and number of samples
+fn read_flac_duration(path: &Path) -> Option<Duration> {
+    let mut header = [0; 42];
+    File::open(path).ok()?.read_exact(&mut header).ok()?;
+    if &header[..4] != b"fLaC" || header[4] & 0x7f != 0 {
+        return None;
+    }
+    let info = read_u64(&header, 18)?;
+    let sample_rate = info >> 44;
+    let samples = info & 0xf_ffff_ffff;
+    if sample_rate == 0 || samples == 0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(samples as f64 / sample_rate as f64))
+}
+
+// The size of the data chunk divided by the byte rate of the fmt chunk
+fn read_wav_duration(path: &Path) -> Option<Duration> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0; 12];
+    file.read_exact(&mut header).ok()?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+        return None;
+    }
+    let mut byte_rate = None;
+    loop {
+        let mut chunk = [0; 8];
+        file.read_exact(&mut chunk).ok()?;
+        let size = u32::from_le_bytes(chunk[4..].try_into().ok()?) as u64;
+        match &chunk[..4] {
+            b"fmt " => {
+                let mut fmt = [0; 12];
+                file.read_exact(&mut fmt).ok()?;
+                byte_rate = Some(u32::from_le_bytes(fmt[8..].try_into().ok()?) as u64);
+                file.seek(SeekFrom::Current(size as i64 - 12 + (size % 2) as i64))
+                    .ok()?;
+            }
+            b"data" => {
+                let byte_rate = byte_rate.filter(|f| *f > 0)?;
+                return Some(Duration::from_secs_f64(size as f64 / byte_rate as f64));
+            }
+            // Chunks are padded to an even size
+            _ => {
+                file.seek(SeekFrom::Current((size + size % 2) as i64)).ok()?;
+            }
+        }
+    }
+}
+
+// The COMM chunk stores the number of sample frames and the sample rate
+fn read_aiff_duration(path: &Path) -> Option<Duration> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0; 12];
+    file.read_exact(&mut header).ok()?;
+    if &header[..4] != b"FORM" || !matches!(&header[8..], b"AIFF" | b"AIFC") {
+        return None;
+    }
+    loop {
+        let mut chunk = [0; 8];
+        file.read_exact(&mut chunk).ok()?;
+        let size = read_u32(&chunk, 4)? as u64;
+        if &chunk[..4] == b"COMM" {
+            let mut comm = [0; 18];
+            file.read_exact(&mut comm).ok()?;
+            let frames = read_u32(&comm, 2)?;
+            // The sample rate is an 80 bit extended precision float
+            let exponent = (u16::from_be_bytes([comm[8], comm[9]]) & 0x7fff) as i32 - 16383;
+            let mantissa = read_u64(&comm, 10)?;
+            let sample_rate = mantissa as f64 * 2f64.powi(exponent - 63);
+            if sample_rate <= 0.0 {
+                return None;
+            }
+            return Some(Duration::from_secs_f64(frames as f64 / sample_rate));
+        }
+        // Chunks are padded to an even size
+        file.seek(SeekFrom::Current((size + size % 2) as i64)).ok()?;
+    }
+}
+
+// The granule position of the last page is the number of samples, the sample rate is stored in
+// the identification header. Opus always counts at 48 kHz, starting after the pre-skip samples.
+fn read_ogg_duration(path: &Path) -> Option<Duration> {
+    let packets = read_ogg_packets(path, 1)?;
+    let identification = packets.first()?;
+    let (sample_rate, pre_skip) = match identification.strip_prefix(b"OpusHead") {
+        Some(head) => (48000, u16::from_le_bytes(head.get(2..4)?.try_into().ok()?) as u64),
+        None => {
+            let vorbis = identification.strip_prefix(b"\x01vorbis")?;
+            (u32::from_le_bytes(vorbis.get(5..9)?.try_into().ok()?), 0)
+        }
+    };
+    if sample_rate == 0 {
+        return None;
+    }
+    let mut file = File::open(path).ok()?;
+    let length = file.metadata().ok()?.len();
+    // Pages are at most 65307 bytes long
+    file.seek(SeekFrom::Start(length.saturating_sub(65536))).ok()?;
+    let mut data = vec![];
+    file.read_to_end(&mut data).ok()?;
+    let start = data.windows(4).rposition(|f| f == b"OggS")?;
+    let samples = u64::from_le_bytes(data.get(start + 6..start + 14)?.try_into().ok()?)
+        .saturating_sub(pre_skip);
+    Some(Duration::from_secs_f64(samples as f64 / sample_rate as f64))
+}
+
+// The movie header stores the duration in units of its timescale
+fn read_mp4_duration(path: &Path) -> Option<Duration> {
+    let mut file = File::open(path).ok()?;
+    let moov = read_top_level_box(&mut file, b"moov")?;
+    let mvhd = find_box(&moov, &[b"mvhd"])?;
+    let (timescale, duration) = match mvhd.first()? {
+        1 => (read_u32(mvhd, 20)?, read_u64(mvhd, 24)?),
+        _ => (read_u32(mvhd, 12)?, read_u32(mvhd, 16)? as u64),
+    };
+    if timescale == 0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(duration as f64 / timescale as f64))
+}
+
+fn decode_duration(path: &Path) -> Option<Duration> {
+    let file = File::open(path).ok()?;
+    Decoder::new(BufReader::new(file)).ok()?.total_duration()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The first frame of an MPEG 1 layer III stream at 44.1 kHz, with the VBR header at `offset`
+    fn frame(mode: u8, offset: usize, header: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xff, 0xfb, 0x90, mode];
+        data.resize(offset, 0);
+        data.extend(header);
+        data.resize(offset + 64, 0);
+        data
+    }
+
+    #[test]
+    fn vbr_headers() {
+        let duration = Duration::from_secs_f64(1000.0 * 1152.0 / 44100.0);
+        let xing = [b"Xing".as_slice(), &[0, 0, 0, 1], &1000u32.to_be_bytes()].concat();
+        assert_eq!(read_vbr_header(&frame(0x00, 36, &xing)), Some(duration));
+        // Mono frames have a shorter side info
+        assert_eq!(read_vbr_header(&frame(0xc0, 21, &xing)), Some(duration));
+        let vbri = [b"VBRI".as_slice(), &[0; 10], &1000u32.to_be_bytes()].concat();
+        assert_eq!(read_vbr_header(&frame(0x00, 36, &vbri)), Some(duration));
+        // Garbage in front of the first frame is skipped
+        let mut data = vec![0x12, 0x34];
+        data.extend(frame(0x00, 36, &xing));
+        assert_eq!(read_vbr_header(&data), Some(duration));
+    }
+
+    #[test]
+    fn malformed_vbr_headers() {
+        assert_eq!(read_vbr_header(&[]), None);
+        assert_eq!(read_vbr_header(&[0xff, 0xfb]), None);
+        // Constant bitrate files have no VBR header
+        assert_eq!(read_vbr_header(&frame(0x00, 36, &[])), None);
+        // The number of frames is optional
+        let xing = [b"Xing".as_slice(), &[0, 0, 0, 0], &1000u32.to_be_bytes()].concat();
+        assert_eq!(read_vbr_header(&frame(0x00, 36, &xing)), None);
+        // The header ends before the number of frames
+        let mut data = frame(0x00, 36, b"Xing\0\0\0\x01");
+        data.truncate(44);
+        assert_eq!(read_vbr_header(&data), None);
+        // The reserved MPEG version
+        let mut data = frame(0x00, 36, b"Xing\0\0\0\x01\0\0\x03\xe8");
+        data[1] = 0xeb;
+        assert_eq!(read_vbr_header(&data), None);
+    }
+}
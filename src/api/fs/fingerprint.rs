@@ -0,0 +1,302 @@
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::PI,
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+use threadpool::ThreadPool;
+
+use super::{cache_path, index::FileSignature};
+
+// The audio is analyzed at a low sample rate, as only the notes below 3.5 kHz are used
+const SAMPLE_RATE: usize = 11025;
+const FRAME_SIZE: usize = 4096;
+const FRAME_STEP: usize = FRAME_SIZE / 3;
+// Only the start of a song is analyzed, which is enough to tell recordings apart
+const MAX_SECONDS: usize = 120;
+// Frames two fingerprints may be shifted by, e.g. for different silence at the start
+const MAX_OFFSET: isize = 24;
+// Frames that have to overlap for fingerprints to be compared
+const MIN_OVERLAP: usize = 50;
+// Share of equal bits of the same recording in different encodings
+const MIN_SIMILARITY: f64 = 0.8;
+// Every frame is described by 24 bits, see frame_hash
+const FRAME_BITS: u32 = 24;
+// Fingerprints computed between saves, so quitting during a long run keeps most of them
+const SAVE_INTERVAL: usize = 100;
+
+// Progress of computing the fingerprints of new and changed files
+#[derive(Clone, Default)]
+pub struct FingerprintProgress {
+    pub total: usize,
+    pub processed: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredFingerprint {
+    signature: FileSignature,
+    fingerprint: Vec<u32>,
+}
+
+// The fingerprints of the files of the library, computed from their decoded audio
+#[derive(Clone, Default)]
+pub struct Fingerprints {
+    files: Arc<Mutex<HashMap<PathBuf, StoredFingerprint>>>,
+    progress: Arc<Mutex<FingerprintProgress>>,
+}
+
+impl Fingerprints {
+    pub fn load() -> Fingerprints {
+        let files = cache_path("fingerprints")
+            .and_then(File::open)
+            .ok()
+            .and_then(|f| serde_json::from_reader(BufReader::new(f)).ok())
+            .unwrap_or_default();
+        Fingerprints {
+            files: Arc::new(Mutex::new(files)),
+            ..Default::default()
+        }
+    }
+    fn save(&self) -> Result<(), io::Error> {
+        let path = cache_path("fingerprints")?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string(&*self.files.lock().unwrap())?)?;
+        fs::rename(tmp_path, path)
+    }
+    pub fn get(&self, path: &Path) -> Option<Vec<u32>> {
+        Some(self.files.lock().unwrap().get(path)?.fingerprint.to_owned())
+    }
+    // Keeps the fingerprints of files that were moved, so they aren't decoded again
+    pub fn rename(&self, from: &Path, to: &Path) {
+        let mut files = self.files.lock().unwrap();
+        if let Some(fingerprint) = files.remove(from) {
+            files.insert(to.to_path_buf(), fingerprint);
+        }
+    }
+    pub fn get_progress(&self) -> FingerprintProgress {
+        self.progress.lock().unwrap().clone()
+    }
+    // Computes the fingerprints of the files received after scans in the background. Only the
+    // files of the latest list are kept, new and changed ones are decoded.
+    pub fn watch(&self, rx: Receiver<Vec<PathBuf>>) {
+        let fingerprints = self.clone();
+        thread::spawn(move || {
+            while let Ok(mut paths) = rx.recv() {
+                // Lists sent while decoding are outdated by the newest one
+                while let Ok(newer) = rx.try_recv() {
+                    paths = newer;
+                }
+                fingerprints.update(paths);
+            }
+        });
+    }
+    fn update(&self, paths: Vec<PathBuf>) {
+        let mut files = self.files.lock().unwrap();
+        let count = files.len();
+        let kept = paths.iter().collect::<HashSet<_>>();
+        files.retain(|path, _| kept.contains(path));
+        let removed = count != files.len();
+        let changed = paths
+            .into_iter()
+            .filter_map(|path| Some((FileSignature::read(&path)?, path)))
+            .filter(|(signature, path)| !files.get(path).is_some_and(|f| f.signature == *signature))
+            .collect::<Vec<_>>();
+        drop(files);
+        *self.progress.lock().unwrap() = FingerprintProgress {
+            total: changed.len(),
+            processed: 0,
+        };
+
+        // Decoding is slow, so the files are decoded in parallel
+        let workers = thread::available_parallelism().map_or(4, |f| f.get());
+        let pool = ThreadPool::new(workers);
+        let (tx, rx) = mpsc::channel();
+        for (signature, path) in changed.iter().cloned() {
+            let tx = tx.clone();
+            let progress = self.progress.clone();
+            pool.execute(move || {
+                let fingerprint = compute(&path);
+                progress.lock().unwrap().processed += 1;
+                let _ = tx.send((path, signature, fingerprint));
+            });
+        }
+        drop(tx);
+        // The files are only locked for every fingerprint, as finding duplicates reads them
+        for (i, (path, signature, fingerprint)) in rx.into_iter().enumerate() {
+            // Files that can't be decoded get an empty fingerprint, so they aren't decoded again
+            let fingerprint = fingerprint.unwrap_or_default();
+            self.files
+                .lock()
+                .unwrap()
+                .insert(path, StoredFingerprint { signature, fingerprint });
+            if (i + 1) % SAVE_INTERVAL == 0 {
+                let _ = self.save();
+            }
+        }
+        if removed || !changed.is_empty() {
+            let _ = self.save();
+        }
+    }
+}
+
+// Whether two fingerprints are of the same recording
+pub fn is_match(a: &[u32], b: &[u32]) -> bool {
+    similarity(a, b) >= MIN_SIMILARITY
+}
+
+// The share of equal bits at the best alignment of the fingerprints
+fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    (-MAX_OFFSET..=MAX_OFFSET)
+        .filter_map(|offset| {
+            let (a, b) = match offset {
+                o if o < 0 => (a, b.get(o.unsigned_abs()..)?),
+                o => (a.get(o as usize..)?, b),
+            };
+            let overlap = a.len().min(b.len());
+            if overlap < MIN_OVERLAP {
+                return None;
+            }
+            let errors = a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum::<u32>();
+            Some(1.0 - errors as f64 / (overlap as f64 * FRAME_BITS as f64))
+        })
+        .fold(0.0, f64::max)
+}
+
+// Describes how the notes of a song change, similar to Chromaprint. The energy of the twelve
+// notes of the scale is measured for every frame, and every hash describes which notes are
+// louder than others.
+pub fn compute(path: &Path) -> Option<Vec<u32>> {
+    let samples = decode(path)?;
+    let window = (0..FRAME_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME_SIZE as f32).cos())
+        .collect::<Vec<_>>();
+    let notes = samples
+        .windows(FRAME_SIZE)
+        .step_by(FRAME_STEP)
+        .map(|frame| {
+            let mut real = frame
+                .iter()
+                .zip(&window)
+                .map(|(s, w)| s * w)
+                .collect::<Vec<_>>();
+            let mut imag = vec![0.0; FRAME_SIZE];
+            fft(&mut real, &mut imag);
+            chroma(&real, &imag)
+        })
+        .collect::<Vec<_>>();
+    Some(
+        notes
+            .windows(2)
+            .map(|f| frame_hash(&f[0], &f[1]))
+            .collect(),
+    )
+}
+
+// Decodes the start of a file to mono samples at SAMPLE_RATE
+fn decode(path: &Path) -> Option<Vec<f32>> {
+    let decoder = Decoder::new(BufReader::new(File::open(path).ok()?)).ok()?;
+    let channels = decoder.channels() as usize;
+    let rate = decoder.sample_rate() as usize;
+    if channels == 0 || rate == 0 {
+        return None;
+    }
+    // Every output sample is the average of the input samples it covers
+    let step = rate as f64 / SAMPLE_RATE as f64;
+    let mut samples = Vec::with_capacity(SAMPLE_RATE * MAX_SECONDS);
+    let (mut sum, mut count, mut position) = (0.0, 0, 0.0);
+    let mut frame = 0.0;
+    for (i, sample) in decoder.take(rate * channels * MAX_SECONDS).enumerate() {
+        frame += sample as f32 / i16::MAX as f32;
+        if (i + 1) % channels != 0 {
+            continue;
+        }
+        sum += frame / channels as f32;
+        frame = 0.0;
+        count += 1;
+        position += 1.0;
+        if position >= step {
+            position -= step;
+            samples.push(sum / count as f32);
+            (sum, count) = (0.0, 0);
+        }
+    }
+    Some(samples)
+}
+
+// Energy of the twelve notes, normalized so the volume doesn't matter
+fn chroma(real: &[f32], imag: &[f32]) -> [f32; 12] {
+    let mut notes = [0.0; 12];
+    for bin in 1..FRAME_SIZE / 2 {
+        let frequency = bin as f32 * SAMPLE_RATE as f32 / FRAME_SIZE as f32;
+        if !(28.0..=3520.0).contains(&frequency) {
+            continue;
+        }
+        let note = (12.0 * (frequency / 27.5).log2()).round() as usize % 12;
+        notes[note] += real[bin] * real[bin] + imag[bin] * imag[bin];
+    }
+    let norm = notes.iter().map(|f| f * f).sum::<f32>().sqrt();
+    if norm > f32::EPSILON {
+        notes.iter_mut().for_each(|f| *f /= norm);
+    }
+    notes
+}
+
+// Compares every note to the next one and to the one a minor third up. Two frames are summed,
+// which makes the bits less sensitive to noise added by lossy encoding.
+fn frame_hash(previous: &[f32; 12], current: &[f32; 12]) -> u32 {
+    let energy = |note: usize| previous[note % 12] + current[note % 12];
+    let mut hash = 0;
+    for note in 0..12 {
+        if energy(note) > energy(note + 1) {
+            hash |= 1 << note;
+        }
+        if energy(note) > energy(note + 3) {
+            hash |= 1 << (note + 12);
+        }
+    }
+    hash
+}
+
+// In-place radix-2 FFT, the length has to be a power of two
+fn fft(real: &mut [f32], imag: &mut [f32]) {
+    let n = real.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            real.swap(i, j);
+            imag.swap(i, j);
+        }
+    }
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + length / 2);
+                let t_real = real[b] * cos - imag[b] * sin;
+                let t_imag = real[b] * sin + imag[b] * cos;
+                real[b] = real[a] - t_real;
+                imag[b] = imag[a] - t_imag;
+                real[a] += t_real;
+                imag[a] += t_imag;
+            }
+        }
+        length <<= 1;
+    }
+}
@@ -0,0 +1,384 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use audiotags::{AudioTag, FlacTag, Id3v2Tag, Mp4Tag, Tag, TagType};
+use id3::TagLike;
+
+// How the tags of a format are stored
+#[derive(Clone, Copy, PartialEq)]
+pub enum TagFormat {
+    // FLAC and MP4 tags as read by audiotags
+    Audiotags,
+    // ID3 tags in front of an MP3 stream or as a chunk of a RIFF or IFF file
+    Id3,
+    // The comment header of an Ogg Vorbis or Opus stream
+    VorbisComment,
+}
+
+pub struct AudioFormat {
+    // Extension reported by infer, which identifies the file by its content
+    pub extension: &'static str,
+    pub tags: TagFormat,
+}
+
+// Every format that is added to the library. All of them but Opus are played by rodio, using the
+// decoder noted for each. Opus files are listed, but playing them reports their codec as
+// unsupported. WavPack is missing, as no decoder for it is available.
+pub const FORMATS: &[AudioFormat] = &[
+    // symphonia
+    AudioFormat {
+        extension: "mp3",
+        tags: TagFormat::Id3,
+    },
+    // symphonia
+    AudioFormat {
+        extension: "flac",
+        tags: TagFormat::Audiotags,
+    },
+    // hound
+    AudioFormat {
+        extension: "wav",
+        tags: TagFormat::Id3,
+    },
+    // symphonia
+    AudioFormat {
+        extension: "aiff",
+        tags: TagFormat::Id3,
+    },
+    // lewton, only Ogg streams containing Vorbis
+    AudioFormat {
+        extension: "ogg",
+        tags: TagFormat::VorbisComment,
+    },
+    // no decoder
+    AudioFormat {
+        extension: "opus",
+        tags: TagFormat::VorbisComment,
+    },
+    // symphonia, containing AAC or ALAC
+    AudioFormat {
+        extension: "m4a",
+        tags: TagFormat::Audiotags,
+    },
+    // symphonia, audiobooks in the same container as m4a
+    AudioFormat {
+        extension: "m4b",
+        tags: TagFormat::Audiotags,
+    },
+];
+
+// Finds the format of an audio file, None for other files
+pub fn detect(path: &Path) -> Option<&'static AudioFormat> {
+    // infer doesn't know the audiobook variant of m4a
+    let extension = match path.extension().and_then(|f| f.to_str()) {
+        Some("m4b") => "m4b",
+        _ => infer::get_from_path(path).ok()??.extension(),
+    };
+    // infer reports every Ogg stream but Opus as ogg, including FLAC and Speex ones lewton
+    // can't decode
+    if extension == "ogg" && !read_ogg_packets(path, 1)?.first()?.starts_with(b"\x01vorbis") {
+        return None;
+    }
+    FORMATS.iter().find(|f| f.extension == extension)
+}
+
+// The tags used by the library, independent of the format they're stored in
+#[derive(Default)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u16>,
+    pub disc_number: Option<u16>,
+    pub disc_total: Option<u16>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    // Set by the TCMP frame or COMPILATION field for albums of various artists
+    pub compilation: bool,
+}
+
+pub fn read_tags(path: &Path, format: &AudioFormat) -> Option<Tags> {
+    match format.tags {
+        TagFormat::Audiotags => {
+            // audiotags doesn't know the compilation flag, so the tag is read by the underlying
+            // crates and the flag is taken from there
+            let (tags, compilation): (Box<dyn AudioTag>, bool) = match format.extension {
+                "flac" => {
+                    let tag = metaflac::Tag::read_from_path(path).ok()?;
+                    let compilation = tag
+                        .get_vorbis("COMPILATION")
+                        .and_then(|mut f| f.next())
+                        .is_some_and(|f| f == "1");
+                    (Box::new(FlacTag::from(tag)), compilation)
+                }
+                _ => {
+                    let tag = mp4ameta::Tag::read_from_path(path).ok()?;
+                    let compilation = tag.compilation();
+                    (Box::new(Mp4Tag::from(tag)), compilation)
+                }
+            };
+            Some(Tags {
+                title: tags.title().map(|f| f.to_string()),
+                artist: tags.artist().map(|f| f.to_string()),
+                album_artist: tags.album_artist().map(|f| f.to_string()),
+                album: tags.album_title().map(|f| f.to_string()),
+                track_number: tags.track_number(),
+                disc_number: tags.disc_number(),
+                disc_total: tags.total_discs(),
+                year: tags.date().map(|f| f.year).or(tags.year()),
+                genre: tags.genre().map(|f| f.to_string()),
+                compilation,
+            })
+        }
+        TagFormat::Id3 => {
+            // id3 finds the tag by the container of the file
+            let tags = id3::Tag::read_from_path(path).ok()?;
+            Some(Tags {
+                title: tags.title().map(|f| f.to_string()),
+                artist: tags.artist().map(|f| f.to_string()),
+                album_artist: tags.album_artist().map(|f| f.to_string()),
+                album: tags.album().map(|f| f.to_string()),
+                track_number: tags.track().and_then(|f| f.try_into().ok()),
+                disc_number: tags.disc().and_then(|f| f.try_into().ok()),
+                disc_total: tags.total_discs().and_then(|f| f.try_into().ok()),
+                year: tags.year().or(tags.date_recorded().map(|f| f.year)),
+                // Resolves numeric ID3v1 genres like (17) to their name
+                genre: tags.genre_parsed().map(|f| f.to_string()),
+                compilation: is_id3_compilation(&tags),
+            })
+        }
+        TagFormat::VorbisComment => {
+            let comments = read_vorbis_comments(path)?;
+            let get = |key: &str| comments.get(key).cloned();
+            Some(Tags {
+                title: get("TITLE"),
+                artist: get("ARTIST"),
+                album_artist: get("ALBUMARTIST").or(get("ALBUM ARTIST")),
+                album: get("ALBUM"),
+                // Numbers are sometimes stored together with the total, e.g. 3/12
+                track_number: get("TRACKNUMBER")
+                    .and_then(|f| f.split('/').next()?.trim().parse().ok()),
+                disc_number: get("DISCNUMBER")
+                    .and_then(|f| f.split('/').next()?.trim().parse().ok()),
+                disc_total: get("DISCTOTAL")
+                    .or(get("TOTALDISCS"))
+                    .or(get("DISCNUMBER").and_then(|f| Some(f.split_once('/')?.1.to_string())))
+                    .and_then(|f| f.trim().parse().ok()),
+                year: get("DATE").and_then(|f| f.get(..4)?.parse().ok()),
+                genre: get("GENRE"),
+                compilation: get("COMPILATION").is_some_and(|f| f == "1"),
+            })
+        }
+    }
+}
+
+// Changed tags of a song, None leaves a tag as it is. Numbers set to Some(None) are removed.
+#[derive(Clone, Default)]
+pub struct TagEdit {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<Option<u16>>,
+    pub disc_number: Option<Option<u16>>,
+    pub year: Option<Option<i32>>,
+    pub genre: Option<String>,
+}
+
+// Fails if the tags of a file can't be written, without changing it
+pub fn check_writable(path: &Path, format: &AudioFormat) -> Result<(), io::Error> {
+    if format.tags == TagFormat::VorbisComment {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Writing Ogg comments is not supported: {}", path.display()),
+        ));
+    }
+    OpenOptions::new().write(true).open(path).map(|_| ())
+}
+
+pub fn write_tags(path: &Path, format: &AudioFormat, edit: &TagEdit) -> Result<(), io::Error> {
+    check_writable(path, format)?;
+    match (format.tags, format.extension) {
+        // audiotags doesn't know the ID3 chunk of RIFF and IFF files
+        (TagFormat::Id3, "wav" | "aiff") => write_id3_chunk(path, edit),
+        (_, extension) => {
+            let tag_type = match extension {
+                "mp3" => TagType::Id3v2,
+                "flac" => TagType::Flac,
+                _ => TagType::Mp4,
+            };
+            let mut tags = match Tag::new().with_tag_type(tag_type).read_from_path(path) {
+                Ok(tags) => tags,
+                // MP3 files may have no tag yet
+                Err(_) if extension == "mp3" => Box::new(Id3v2Tag::new()),
+                Err(e) => return Err(io::Error::other(e)),
+            };
+            apply_edit(tags.as_mut(), edit, tag_type);
+            tags.write_to_path(&path.to_string_lossy())
+                .map_err(io::Error::other)
+        }
+    }
+}
+
+fn apply_edit(tags: &mut dyn AudioTag, edit: &TagEdit, tag_type: TagType) {
+    if let Some(title) = &edit.title {
+        tags.set_title(title);
+    }
+    if let Some(artist) = &edit.artist {
+        tags.set_artist(artist);
+    }
+    if let Some(album_artist) = &edit.album_artist {
+        tags.set_album_artist(album_artist);
+    }
+    if let Some(album) = &edit.album {
+        tags.set_album_title(album);
+    }
+    match edit.track_number {
+        Some(Some(track_number)) => tags.set_track_number(track_number),
+        Some(None) => tags.remove_track_number(),
+        None => {}
+    }
+    match edit.disc_number {
+        Some(Some(disc_number)) => tags.set_disc_number(disc_number),
+        Some(None) => tags.remove_disc_number(),
+        None => {}
+    }
+    match edit.year {
+        // The year of FLAC files is read from DATE first, audiotags writes it to YEAR
+        Some(Some(year)) if matches!(tag_type, TagType::Flac) => {
+            tags.remove_year();
+            tags.set_date(id3::Timestamp {
+                year,
+                month: None,
+                day: None,
+                hour: None,
+                minute: None,
+                second: None,
+            });
+        }
+        Some(Some(year)) => tags.set_year(year),
+        Some(None) => tags.remove_year(),
+        None => {}
+    }
+    if let Some(genre) = &edit.genre {
+        tags.set_genre(genre);
+    }
+}
+
+fn write_id3_chunk(path: &Path, edit: &TagEdit) -> Result<(), io::Error> {
+    let mut tags = match id3::Tag::read_from_path(path) {
+        Ok(tags) => tags,
+        Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+        Err(e) => return Err(io::Error::other(e)),
+    };
+    if let Some(title) = &edit.title {
+        tags.set_title(title);
+    }
+    if let Some(artist) = &edit.artist {
+        tags.set_artist(artist);
+    }
+    if let Some(album_artist) = &edit.album_artist {
+        tags.set_album_artist(album_artist);
+    }
+    if let Some(album) = &edit.album {
+        tags.set_album(album);
+    }
+    match edit.track_number {
+        Some(Some(track_number)) => tags.set_track(track_number.into()),
+        Some(None) => tags.remove_track(),
+        None => {}
+    }
+    match edit.disc_number {
+        Some(Some(disc_number)) => tags.set_disc(disc_number.into()),
+        Some(None) => tags.remove_disc(),
+        None => {}
+    }
+    match edit.year {
+        Some(Some(year)) => tags.set_year(year),
+        // The year is also read from the recording date
+        Some(None) => {
+            tags.remove_year();
+            tags.remove_date_recorded();
+        }
+        None => {}
+    }
+    if let Some(genre) = &edit.genre {
+        tags.set_genre(genre);
+    }
+    tags.write_to_path(path, id3::Version::Id3v24)
+        .map_err(io::Error::other)
+}
+
+fn is_id3_compilation(tag: &id3::Tag) -> bool {
+    tag.get("TCMP")
+        .and_then(|f| f.content().text())
+        .is_some_and(|f| f == "1")
+}
+
+// Reads the comment header, the second packet of an Ogg Vorbis or Opus stream. Both store the
+// same fields behind a different magic. Only the first value of every field is kept, with the
+// field names in upper case.
+fn read_vorbis_comments(path: &Path) -> Option<HashMap<String, String>> {
+    let packets = read_ogg_packets(path, 2)?;
+    let header = packets.get(1)?;
+    let comments = header
+        .strip_prefix(b"\x03vorbis")
+        .or(header.strip_prefix(b"OpusTags"))?;
+    let mut pos = 0;
+    // The vendor string comes first
+    read_length_prefixed(comments, &mut pos)?;
+    let mut fields = HashMap::new();
+    let count = u32::from_le_bytes(comments.get(pos..pos + 4)?.try_into().ok()?);
+    pos += 4;
+    for _ in 0..count {
+        let comment = match read_length_prefixed(comments, &mut pos) {
+            Some(c) => c,
+            None => break,
+        };
+        if let Some((key, value)) = comment.split_once('=') {
+            fields
+                .entry(key.to_uppercase())
+                .or_insert(value.to_string());
+        }
+    }
+    Some(fields)
+}
+
+// Reads a string after its length, moving the position behind it
+fn read_length_prefixed(data: &[u8], pos: &mut usize) -> Option<String> {
+    let length = u32::from_le_bytes(data.get(*pos..*pos + 4)?.try_into().ok()?) as usize;
+    let string = String::from_utf8_lossy(data.get(*pos + 4..*pos + 4 + length)?).to_string();
+    *pos += 4 + length;
+    Some(string)
+}
+
+// Reads the first packets of an Ogg file, which may be spread over several pages
+pub(super) fn read_ogg_packets(path: &Path, count: usize) -> Option<Vec<Vec<u8>>> {
+    let mut file = BufReader::new(File::open(path).ok()?);
+    let mut packets = vec![];
+    let mut packet = vec![];
+    while packets.len() < count {
+        let mut header = [0; 27];
+        file.read_exact(&mut header).ok()?;
+        if &header[..4] != b"OggS" {
+            return None;
+        }
+        let mut segments = vec![0; header[26] as usize];
+        file.read_exact(&mut segments).ok()?;
+        for size in segments {
+            let mut data = vec![0; size as usize];
+            file.read_exact(&mut data).ok()?;
+            packet.extend(data);
+            // Packets end with the first segment shorter than 255 bytes
+            if size < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+    }
+    Some(packets)
+}
@@ -0,0 +1,252 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::UNIX_EPOCH,
+};
+
+use chrono::Local;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use threadpool::ThreadPool;
+
+use super::{cache_path, cue, depth_first_search_files, FsSong, SaveableSong};
+
+// Size and modification times of a file, used to notice changes without reading it
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct FileSignature {
+    modified: u64,
+    size: u64,
+    // Cue sheets also depend on their directory, which changes when the referenced image is
+    // added after the sheet
+    dir_modified: Option<u64>,
+}
+
+impl FileSignature {
+    pub(super) fn read(path: &Path) -> Option<FileSignature> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(FileSignature {
+            modified: modified_millis(&metadata)?,
+            size: metadata.len(),
+            dir_modified: if is_cue_sheet(path) {
+                fs::metadata(path.parent()?)
+                    .ok()
+                    .and_then(|f| modified_millis(&f))
+            } else {
+                None
+            },
+        })
+    }
+}
+
+struct IndexedFile {
+    signature: FileSignature,
+    songs: Vec<FsSong>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveableIndexedFile {
+    path: PathBuf,
+    signature: FileSignature,
+    songs: Vec<SaveableSong>,
+}
+
+// State of the running or last scan
+#[derive(Clone, Default)]
+pub struct ScanProgress {
+    pub running: bool,
+    // Files found in the library
+    pub seen: usize,
+    // New or changed files that have to be read
+    pub changed: usize,
+    pub processed: usize,
+    // Audio files that couldn't be read
+    pub failed: usize,
+}
+
+// Every file of the library with the songs read from it. Files without songs are kept as
+// well, so they aren't read again on every scan.
+#[derive(Default)]
+pub struct FileIndex {
+    files: HashMap<PathBuf, IndexedFile>,
+}
+
+impl FileIndex {
+    // Re-reads new and changed files and drops deleted ones, returns whether anything changed
+    pub fn update(&mut self, roots: &[PathBuf], progress: &Arc<Mutex<ScanProgress>>) -> bool {
+        // Files of roots that were removed from the configuration are dropped as well
+        let count = self.files.len();
+        self.files.retain(|path, _| roots.iter().any(|root| path.starts_with(root)));
+        let removed = count != self.files.len();
+        self.update_paths(roots, roots, progress) || removed
+    }
+    // Like update, but only looks at the given files and directories inside of the roots
+    pub fn update_paths(
+        &mut self,
+        roots: &[PathBuf],
+        paths: &[PathBuf],
+        progress: &Arc<Mutex<ScanProgress>>,
+    ) -> bool {
+        *progress.lock().unwrap() = ScanProgress {
+            running: true,
+            ..Default::default()
+        };
+        // Cue sheets change along with the directory of the files they reference
+        let cue_sheets = self
+            .files
+            .keys()
+            .filter(|f| is_cue_sheet(f) && paths.iter().any(|p| f.parent() == p.parent()))
+            .cloned()
+            .collect_vec();
+        let mut found = HashSet::new();
+        let mut changed_files = vec![];
+        for path in paths.iter().chain(&cue_sheets) {
+            let root = match roots.iter().find(|r| path.starts_with(r)) {
+                Some(r) => r,
+                None => continue,
+            };
+            for path in depth_first_search_files(vec![path.to_owned()]) {
+                let signature = match FileSignature::read(&path) {
+                    Some(s) => s,
+                    None => continue,
+                };
+                progress.lock().unwrap().seen += 1;
+                found.insert(path.to_owned());
+                if !self
+                    .files
+                    .get(&path)
+                    .is_some_and(|f| f.signature == signature)
+                {
+                    changed_files.push((path, root.to_owned(), signature));
+                }
+            }
+        }
+        let changed = !changed_files.is_empty();
+        progress.lock().unwrap().changed = changed_files.len();
+        // Only the files of the first scan count as added when they were last modified, so it
+        // doesn't mark the whole library as new. Files found later were added just now.
+        let first_scan = self.files.is_empty();
+        let now = Local::now().timestamp_millis();
+
+        // Reading tags and durations is slow, so the files are read in parallel
+        let workers = thread::available_parallelism().map_or(4, |f| f.get());
+        let pool = ThreadPool::new(workers);
+        let (tx, rx) = mpsc::channel();
+        for (path, root, signature) in changed_files {
+            let tx = tx.clone();
+            let progress = progress.clone();
+            // Changed files keep the time they were first found
+            let added = self
+                .files
+                .get(&path)
+                .and_then(|f| f.songs.iter().find_map(|s| s.added))
+                .unwrap_or(if first_scan { signature.modified as i64 } else { now });
+            pool.execute(move || {
+                let songs = read_songs(&path, &root)
+                    .into_iter()
+                    .map(|f| f.with_added(Some(added)))
+                    .collect_vec();
+                let mut progress = progress.lock().unwrap();
+                progress.processed += 1;
+                if songs.is_empty() && FsSong::audio_extension(&path).is_some() {
+                    progress.failed += 1;
+                }
+                drop(progress);
+                let _ = tx.send((path, IndexedFile { signature, songs }));
+            });
+        }
+        drop(tx);
+        self.files.extend(rx);
+
+        // Files below the given paths that weren't found anymore were deleted
+        let count = self.files.len();
+        self.files
+            .retain(|path, _| found.contains(path) || !paths.iter().any(|p| path.starts_with(p)));
+        progress.lock().unwrap().running = false;
+        changed || count != self.files.len()
+    }
+    // Moves the entry of a file that was moved by mplayer, so its songs keep the time they were
+    // added. Files whose signature changed by the move are read again on the next update.
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        if let Some(mut file) = self.files.remove(from) {
+            for song in &mut file.songs {
+                song.path = to.to_path_buf();
+            }
+            self.files.insert(to.to_path_buf(), file);
+        }
+    }
+    // All songs of the library, ordered by their path
+    pub fn songs(&self) -> Vec<FsSong> {
+        // Files referenced by a cue sheet are replaced by its tracks
+        let cue_files = self
+            .files
+            .iter()
+            .filter(|(path, _)| is_cue_sheet(path))
+            .flat_map(|(_, f)| f.songs.iter().map(|s| s.path.to_owned()))
+            .collect::<HashSet<PathBuf>>();
+        self.files
+            .iter()
+            .filter(|(path, _)| is_cue_sheet(path) || !cue_files.contains(*path))
+            .flat_map(|(_, f)| f.songs.to_owned())
+            .sorted_by(|a, b| a.path.cmp(&b.path).then(a.start.cmp(&b.start)))
+            .collect()
+    }
+    pub fn load() -> Result<FileIndex, io::Error> {
+        let file = File::open(cache_path("file_index")?)?;
+        let files: Vec<SaveableIndexedFile> = serde_json::from_reader(BufReader::new(file))?;
+        Ok(FileIndex {
+            files: files
+                .into_iter()
+                .map(|f| {
+                    (
+                        f.path,
+                        IndexedFile {
+                            signature: f.signature,
+                            songs: f.songs.into_iter().map(SaveableSong::into_song).collect(),
+                        },
+                    )
+                })
+                .collect(),
+        })
+    }
+    pub fn save(&self) -> Result<(), io::Error> {
+        let files = self
+            .files
+            .iter()
+            .map(|(path, f)| SaveableIndexedFile {
+                path: path.to_owned(),
+                signature: f.signature.to_owned(),
+                songs: f.songs.iter().map(|s| SaveableSong::from_song(s)).collect(),
+            })
+            .collect::<Vec<SaveableIndexedFile>>();
+        let path = cache_path("file_index")?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string(&files)?)?;
+        fs::rename(tmp_path, path)
+    }
+}
+
+fn read_songs(path: &Path, root: &Path) -> Vec<FsSong> {
+    let songs = if is_cue_sheet(path) {
+        cue::read_cue_sheet(path)
+    } else {
+        FsSong::new(path.to_path_buf()).into_iter().collect()
+    };
+    songs
+        .into_iter()
+        .map(|f| f.with_root(root.to_path_buf()))
+        .collect()
+}
+
+fn is_cue_sheet(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("cue"))
+}
+
+fn modified_millis(metadata: &fs::Metadata) -> Option<u64> {
+    let modified = metadata.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64)
+}
+
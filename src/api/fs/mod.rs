@@ -1,49 +1,147 @@
+mod chapters;
+mod cue;
+mod duration;
+mod duplicates;
+mod fingerprint;
+mod formats;
+mod index;
+mod organize;
+mod trash;
+mod watcher;
+
 use std::{
+    collections::{HashMap, HashSet},
     fs::{self, File},
     io::{self, Cursor, Read},
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
     thread,
+    time,
 };
 
-use super::{Album, Artist, Deleteable, Song};
+use super::{Album, Artist, Chapter, Deleteable, Song};
 use crate::api::player::SongInfo;
-use audiotags::Tag;
-use chrono::Duration;
+use fingerprint::Fingerprints;
+use index::FileIndex;
+use watcher::Change;
+pub use duplicates::{Duplicate, DuplicateProgress, DuplicateSearch};
+pub use fingerprint::FingerprintProgress;
+pub use formats::TagEdit;
+pub use index::ScanProgress;
+pub use organize::PlannedMove;
+pub use trash::disk_usage;
+use chrono::{DateTime, Duration, Local, TimeZone};
 use dirs::cache_dir;
 use itertools::Itertools;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+// How long the library has to stay unchanged before changes are applied
+const WATCH_DEBOUNCE: time::Duration = time::Duration::from_secs(2);
+// Album artist of compilations that don't name one
+const VARIOUS_ARTISTS: &str = "Various Artists";
+// Untagged albums with this many performers are treated as compilations
+const COMPILATION_ARTISTS: usize = 3;
+
 #[derive(Clone)]
 pub struct MusicRepository {
-    path: PathBuf,
+    roots: Vec<PathBuf>,
     artists: Arc<Mutex<Vec<Box<dyn Artist + Send + Sync>>>>,
+    // Counts the changes of the artists, so lists derived from them are only built once each
+    version: Arc<AtomicUsize>,
+    rescan_interval: time::Duration,
+    rescan_requested: Arc<AtomicBool>,
+    // Files moved by organizing, whose index entries are moved before the next scan
+    moved_files: Arc<Mutex<Vec<(PathBuf, PathBuf)>>>,
+    scan_progress: Arc<Mutex<ScanProgress>>,
+    // Fingerprints of the audio, used to find duplicates with different tags
+    fingerprints: Option<Fingerprints>,
 }
 
 impl MusicRepository {
-    pub fn new(path: PathBuf) -> MusicRepository {
+    pub fn new(roots: Vec<PathBuf>) -> MusicRepository {
         MusicRepository {
-            path,
+            roots,
             artists: Arc::new(Mutex::new(vec![])),
+            version: Arc::new(AtomicUsize::new(0)),
+            rescan_interval: time::Duration::from_secs(300),
+            rescan_requested: Arc::new(AtomicBool::new(false)),
+            moved_files: Arc::new(Mutex::new(vec![])),
+            scan_progress: Arc::new(Mutex::new(ScanProgress::default())),
+            fingerprints: None,
         }
     }
-    fn scan_repository(path: PathBuf) -> Vec<Box<dyn Artist + Send + Sync>> {
-        let files = depth_first_search_files(vec![path]);
-        files
+    pub fn with_rescan_interval(mut self, interval: time::Duration) -> MusicRepository {
+        self.rescan_interval = interval;
+        self
+    }
+    // Computing fingerprints decodes every file once, which takes a while for large libraries
+    pub fn with_fingerprints(mut self, enabled: bool) -> MusicRepository {
+        self.fingerprints = enabled.then(Fingerprints::load);
+        self
+    }
+    // Finds compilations without a compilation tag, which would be spread over the artists of
+    // their tracks: albums of a single directory with several performers that share one album
+    // artist, which is either missing or none of the performers
+    fn detect_compilations(songs: Vec<FsSong>) -> Vec<FsSong> {
+        let album_key = |f: &FsSong| (f.path.parent().map(|p| p.to_owned()), f.get_album_name());
+        let compilations = songs
+            .iter()
+            .into_group_map_by(|f| album_key(f))
+            .into_iter()
+            .filter(|(key, album)| {
+                // Guest appearances don't make an album a compilation, so only the lead artists
+                // of the tracks are compared
+                let artists = album
+                    .iter()
+                    .filter_map(|f| f.artist.as_deref())
+                    .map(lead_artist)
+                    .unique()
+                    .collect::<Vec<_>>();
+                let shared_album_artist = album.iter().map(|f| &f.album_artist).all_equal_value();
+                !key.1.is_empty()
+                    && artists.len() >= COMPILATION_ARTISTS
+                    && match shared_album_artist {
+                        Ok(Some(album_artist)) => !artists.contains(&lead_artist(album_artist)),
+                        Ok(None) => true,
+                        Err(_) => false,
+                    }
+            })
+            .map(|f| f.0)
+            .collect::<HashSet<_>>();
+        songs
+            .into_iter()
+            .map(|mut f| {
+                if compilations.contains(&album_key(&f)) {
+                    f.album_artist = Some(VARIOUS_ARTISTS.to_string());
+                }
+                f
+            })
+            .collect()
+    }
+    fn group_songs(songs: Vec<FsSong>) -> Vec<Box<dyn Artist + Send + Sync>> {
+        MusicRepository::detect_compilations(songs)
             .into_iter()
-            // Convert filesystem path into song struct containing metadata
-            .map( FsSong::new)
-            // Ignore invalid files and convert valid ones into Song trait
-            .filter(|f| f.is_some())
-            .map(|f| Box::new(f.unwrap()) as Box<dyn Song + Send + Sync>)
-            // Group the songs by their album name
-            .chunk_by(|f| f.get_album_name())
+            // A file is only added once, the tracks of a cue sheet differ in their start
+            .unique_by(|f| (f.path.to_owned(), f.start))
+            // Group the songs by album, no matter where their files are in the library. The discs
+            // of an album stay together and are ordered by FsAlbum.
+            .into_group_map_by(|f| (f.get_album_artist_name(), f.get_album_name()))
             .into_iter()
+            .sorted_by(|a, b| a.0.cmp(&b.0))
             // Create an FsAlbum from the grouped song and convert it into an Album trait
-            .map(|f| Box::new(FsAlbum::new(f.1.unique_by(|song| song.get_title()).collect_vec())) as Box<dyn Album + Send + Sync>)
-            // Group the albums by the album artist name
-            .chunk_by(|f| f.get_songs()[0].get_artist_name())
+            .map(|f| {
+                Box::new(FsAlbum::new(
+                    f.1.into_iter()
+                        .map(|song| Box::new(song) as Box<dyn Song + Send + Sync>)
+                        .collect(),
+                )) as Box<dyn Album + Send + Sync>
+            })
+            // The albums are sorted by the album artist name, so each artist is a single chunk
+            .chunk_by(|f| f.get_artist_name())
             .into_iter()
             // Create an FsArtist from the grouped albums and convert it into an Artist trait
             .map(|f| {
@@ -53,142 +151,54 @@ impl MusicRepository {
             .collect_vec()
     }
     pub fn remove_artist(&mut self, artist: Box<dyn Artist + Send + Sync>) {
-        let cloned = self.clone();
-        let mut artists = cloned.artists.lock().unwrap();
-        // Search index of the artist to be removed
-        let index = artists
+        let songs = artist
+            .get_albums()
             .iter()
-            .position(|x| *x.get_name() == artist.get_name())
-            .unwrap();
-        // Remove the artist
-        let mut new_artists = artists.to_owned();
-        new_artists.remove(index);
-        *artists = new_artists;
-        // Force to repopulate cache
-        let _ = self.cache_artists();
+            .flat_map(|album| album.get_songs())
+            .collect_vec();
+        self.remove_songs(&songs);
     }
     pub fn remove_album(&mut self, album: Box<dyn Album + Send + Sync>) {
-        let cloned = self.clone();
-        let mut artists = cloned.artists.lock().unwrap();
-        let artist_index = artists
-            .iter()
-            .position(|x| *x.get_name() == album.get_songs().first().unwrap().get_artist_name())
-            .unwrap();
-
-        let artist = artists.get(artist_index).unwrap();
-
-        let new_artist = Box::new(FsArtist::new_2(
-            artist
-                .get_albums()
-                .iter()
-                .filter(|x| x.get_name() != album.get_name())
-                .map(|f| f.to_owned())
-                .collect(),
-            artist.get_name(),
-        )) as Box<dyn Artist + Send + Sync>;
-
-        let mut new_artists = artists.to_owned();
-        new_artists[artist_index] = new_artist;
-        *artists = new_artists;
-        let _ = self.cache_artists();
+        self.remove_songs(&album.get_songs());
     }
     pub fn remove_song(&mut self, song: Box<dyn Song + Send + Sync>) {
-        let cloned = self.clone();
-        let mut artists = cloned.artists.lock().unwrap();
-        let artist_index = artists
-            .iter()
-            .position(|x| *x.get_name() == song.get_artist_name())
-            .unwrap();
-
-        let artist = artists.get(artist_index).unwrap();
-        let album_index = artist
-            .get_albums()
+        self.remove_songs(&[song]);
+    }
+    // Removes deleted songs without waiting for a rescan. The rest is grouped again, so albums
+    // and artists without songs disappear as well.
+    fn remove_songs(&mut self, removed: &[Box<dyn Song + Send + Sync>]) {
+        let removed = removed
             .iter()
-            .position(|x| *x.get_name() == song.get_album_name())
-            .unwrap();
-
-        let new_songs = artist
-            .get_albums()
-            .get(album_index)
-            .unwrap()
-            .get_songs()
+            .map(|f| (f.get_filepath(), f.get_start()))
+            .collect::<HashSet<_>>();
+        let mut artists = self.artists.lock().unwrap();
+        let songs = artists
             .iter()
-            .filter(|x| x.get_title() != song.get_title())
-            .map(|f| f.to_owned())
+            .flat_map(|artist| artist.get_albums())
+            .flat_map(|album| album.get_songs())
+            .filter(|f| !removed.contains(&(f.get_filepath(), f.get_start())))
+            .map(|f| SaveableSong::from_song(f.as_ref()).into_song())
+            .sorted_by(|a, b| a.path.cmp(&b.path).then(a.start.cmp(&b.start)))
             .collect();
-
-        let new_album = Box::new(FsAlbum::new(new_songs)) as Box<dyn Album + Send + Sync>;
-        let mut new_albums = artist.get_albums();
-        new_albums[album_index] = new_album;
-        let new_artist = Box::new(FsArtist::new_2(new_albums, artist.get_name()));
-        let mut new_artists = artists.to_owned();
-        new_artists[artist_index] = new_artist;
-        *artists = new_artists;
+        *artists = MusicRepository::group_songs(songs);
+        self.version.fetch_add(1, Ordering::Relaxed);
+        // The lock has to be released, caching locks the artists again
+        drop(artists);
         let _ = self.cache_artists();
     }
-    pub fn get_artists(&mut self) -> Vec<Box<dyn Artist + Send + Sync>> {
+    pub fn get_artists(&self) -> Vec<Box<dyn Artist + Send + Sync>> {
         self.artists.lock().unwrap().clone()
     }
+    // Changes whenever the artists change
+    pub fn get_version(&self) -> usize {
+        self.version.load(Ordering::Relaxed)
+    }
     pub fn cache_artists(&mut self) -> Result<(), io::Error> {
         let artists = self.artists.lock().unwrap().clone();
-
-        let data = serde_json::to_string(
-            &artists
-                .into_iter()
-                .map(|f| SaveableArtist {
-                    name: f.get_name(),
-                    albums: f
-                        .get_albums()
-                        .into_iter()
-                        .map(|f| SaveableAlbum {
-                            name: f.get_name(),
-                            songs: f
-                                .get_songs()
-                                .into_iter()
-                                .filter(|f| f.get_filepath().is_some())
-                                .map(|f| SaveableSong {
-                                    path: f.get_filepath().unwrap(),
-                                    title: f.get_title(),
-                                    length: time_to_millis(
-                                        f.get_length().unwrap_or("00:00".to_string()),
-                                    )
-                                    .unwrap_or(0 as f64),
-                                    number: f
-                                        .get_number()
-                                        .unwrap_or("0".to_string())
-                                        .parse()
-                                        .unwrap_or(0),
-                                    release_data: f.get_release_date().unwrap_or("0".to_string()),
-                                    album_name: f.get_album_name(),
-                                })
-                                .collect(),
-                        })
-                        .collect(),
-                })
-                .collect::<Vec<SaveableArtist>>(),
-        )?;
-
-        let mut path = cache_dir().ok_or(io::Error::new(
-            io::ErrorKind::Other,
-            "Failed to find config dir",
-        ))?;
-        path.push("mplayer");
-        fs::create_dir_all(&path)?;
-        path.push("artist_cache");
-        let mut file = File::create(&path)?;
-        let mut content = Cursor::new(data);
-        io::copy(&mut content, &mut file)?;
-        Ok(())
+        save_artists(&artists)
     }
     pub fn load_cached_artists(&mut self) -> Result<(), io::Error> {
-        let mut path = cache_dir().ok_or(io::Error::new(
-            io::ErrorKind::Other,
-            "Failed to find config dir",
-        ))?;
-        path.push("mplayer");
-        path.push("artist_cache");
-
-        let mut file = File::open(path)?;
+        let mut file = File::open(cache_path("artist_cache")?)?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
 
@@ -203,17 +213,7 @@ impl MusicRepository {
                             Box::new(FsAlbum::new(
                                 f.songs
                                     .into_iter()
-                                    .map(|f| {
-                                        Box::new(FsSong::fastnew(
-                                            f.path,
-                                            f.title,
-                                            f.length,
-                                            f.number,
-                                            f.album_name,
-                                            f.release_data,
-                                        ))
-                                            as Box<dyn Song + Send + Sync>
-                                    })
+                                    .map(|f| Box::new(f.into_song()) as Box<dyn Song + Send + Sync>)
                                     .collect(),
                             )) as Box<dyn Album + Send + Sync>
                         })
@@ -224,29 +224,359 @@ impl MusicRepository {
             .collect();
         let mut guard = self.artists.lock().unwrap();
         *guard = artists;
+        self.version.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
+    // Keeps the artists up to date with the library roots. Changes are picked up by watching the
+    // roots and applied once no more changes happened for a moment, so copying an album causes
+    // a single update. If the roots can't be watched, or the watcher stops, the library is
+    // rescanned periodically.
     pub fn watch_files(&self) {
         let artists = self.artists.clone();
-        let path = self.path.clone();
-        thread::spawn(move || loop {
-            let scanned_artists = MusicRepository::scan_repository(path.to_owned());
-            let mut artist_lock = artists.lock().unwrap();
-            *artist_lock = scanned_artists;
+        let version = self.version.clone();
+        let roots = self.roots.clone();
+        let interval = self.rescan_interval;
+        let requested = self.rescan_requested.clone();
+        let moved_files = self.moved_files.clone();
+        let progress = self.scan_progress.clone();
+        let (tx, rx) = mpsc::channel();
+        let mut watching = watcher::watch(&roots, tx).is_ok();
+        // The files of the library are passed on after every change to fingerprint new ones
+        let fingerprint_tx = self.fingerprints.as_ref().map(|fingerprints| {
+            let (tx, rx) = mpsc::channel();
+            fingerprints.watch(rx);
+            tx
+        });
+        thread::spawn(move || {
+            let mut index = FileIndex::load().unwrap_or_default();
+            // Changes made while mplayer wasn't running
+            let mut changed = index.update(&roots, &progress);
+            let mut scanned_at = time::Instant::now();
+            let mut pending = HashSet::new();
+            let mut last_change = time::Instant::now();
+            let mut fingerprinted = false;
+            let mut overflowed = false;
+            loop {
+                if changed {
+                    let scanned_artists = MusicRepository::group_songs(index.songs());
+                    let _ = index.save();
+                    let _ = save_artists(&scanned_artists);
+                    *artists.lock().unwrap() = scanned_artists;
+                    version.fetch_add(1, Ordering::Relaxed);
+                }
+                if let Some(tx) = fingerprint_tx.as_ref().filter(|_| changed || !fingerprinted) {
+                    // Tracks of cue sheets aren't compared, see duplicates::find
+                    let files = index
+                        .songs()
+                        .into_iter()
+                        .filter(|f| f.start.is_none())
+                        .map(|f| f.path)
+                        .collect();
+                    let _ = tx.send(files);
+                    fingerprinted = true;
+                }
+                match rx.recv_timeout(time::Duration::from_millis(200)) {
+                    Ok(Change::Path(path)) => {
+                        pending.insert(path);
+                        last_change = time::Instant::now();
+                    }
+                    // Changes were lost, which only a full scan can pick up
+                    Ok(Change::Overflow) => {
+                        overflowed = true;
+                        last_change = time::Instant::now();
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // Nothing is watched, so the channel can't be used for waiting
+                    Err(RecvTimeoutError::Disconnected) => {
+                        watching = false;
+                        thread::sleep(time::Duration::from_millis(200))
+                    }
+                }
+                for (from, to) in moved_files.lock().unwrap().drain(..) {
+                    index.rename(&from, &to);
+                }
+                changed = if requested.swap(false, Ordering::Relaxed)
+                    || (overflowed && last_change.elapsed() >= WATCH_DEBOUNCE)
+                    || (!watching && scanned_at.elapsed() >= interval)
+                {
+                    overflowed = false;
+                    pending.clear();
+                    scanned_at = time::Instant::now();
+                    index.update(&roots, &progress)
+                } else if !pending.is_empty() && last_change.elapsed() >= WATCH_DEBOUNCE {
+                    let paths = pending.drain().collect_vec();
+                    index.update_paths(&roots, &paths, &progress)
+                } else {
+                    false
+                };
+            }
         });
     }
-    pub fn find_current_album(&self, song_info: &SongInfo) -> Option<Box<dyn Album + Send + Sync>> {
+    pub fn get_scan_progress(&self) -> ScanProgress {
+        self.scan_progress.lock().unwrap().clone()
+    }
+    pub fn get_fingerprint_progress(&self) -> Option<FingerprintProgress> {
+        Some(self.fingerprints.as_ref()?.get_progress())
+    }
+    pub fn rescan(&self) {
+        self.rescan_requested.store(true, Ordering::Relaxed);
+    }
+    // All genres of the library, sorted by name
+    pub fn get_genres(&self) -> Vec<String> {
         self.artists
             .lock()
             .unwrap()
             .iter()
-            .filter(|artist| artist.get_name() == song_info.artist)
-            .nth(0)?
-            .get_albums()
+            .flat_map(|artist| artist.get_albums())
+            .flat_map(|album| album.get_songs())
+            .filter_map(|song| song.get_genre())
+            .unique()
+            .sorted_by_key(|f| f.to_lowercase())
+            .collect()
+    }
+    // The artists with albums of a genre, each only listing these albums
+    pub fn get_genre_artists(&self, genre: &str) -> Vec<Box<dyn Artist + Send + Sync>> {
+        self.artists
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|artist| {
+                let albums = artist
+                    .get_albums()
+                    .into_iter()
+                    .filter(|album| {
+                        album
+                            .get_songs()
+                            .iter()
+                            .any(|song| song.get_genre().is_some_and(|f| f == genre))
+                    })
+                    .collect_vec();
+                if albums.is_empty() {
+                    None
+                } else {
+                    Some(Box::new(FsArtist::new_2(albums, artist.get_name()))
+                        as Box<dyn Artist + Send + Sync>)
+                }
+            })
+            .collect()
+    }
+    // The decades albums were released in, like 1990. Albums without a year are listed as None
+    // after the decades.
+    pub fn get_decades(&self) -> Vec<Option<i32>> {
+        self.get_albums()
+            .iter()
+            .map(|f| album_year(f.as_ref()).map(|y| y - y.rem_euclid(10)))
+            .unique()
+            .sorted_by_key(|f| (f.is_none(), *f))
+            .collect()
+    }
+    // The years of a decade albums were released in
+    pub fn get_years(&self, decade: i32) -> Vec<i32> {
+        self.get_albums()
+            .iter()
+            .filter_map(|f| album_year(f.as_ref()))
+            .filter(|f| (decade..decade + 10).contains(f))
+            .unique()
+            .sorted()
+            .collect()
+    }
+    // The albums released in a year, or the albums without a year for None
+    pub fn get_year_albums(&self, year: Option<i32>) -> Vec<Box<dyn Album + Send + Sync>> {
+        self.get_albums()
+            .into_iter()
+            .filter(|f| album_year(f.as_ref()) == year)
+            .sorted_by_key(|f| (f.get_artist_name().to_lowercase(), f.get_name()))
+            .collect()
+    }
+    // Albums with songs added within the window, the newest first
+    pub fn get_recently_added(&self, window: time::Duration) -> Vec<Box<dyn Album + Send + Sync>> {
+        // Windows reaching before the earliest date include everything
+        let since = Duration::from_std(window)
+            .ok()
+            .and_then(|f| Local::now().checked_sub_signed(f));
+        self.get_albums()
+            .into_iter()
+            .filter_map(|album| Some((album_added(album.as_ref())?, album)))
+            .filter(|(added, _)| since.is_none_or(|f| *added >= f))
+            .sorted_by_key(|(added, _)| std::cmp::Reverse(*added))
+            .map(|(_, album)| album)
+            .collect()
+    }
+    fn get_albums(&self) -> Vec<Box<dyn Album + Send + Sync>> {
+        self.artists
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|artist| artist.get_albums())
+            .collect()
+    }
+    // Writes the changed tags to the files of the songs and updates the library right away,
+    // instead of waiting for the changed files to be read again
+    pub fn edit_tags(
+        &mut self,
+        songs: &[Box<dyn Song + Send + Sync>],
+        edit: &TagEdit,
+    ) -> Result<(), io::Error> {
+        let mut files = vec![];
+        for song in songs {
+            // The tracks of a cue sheet share a file and get their tags from the sheet
+            if song.get_start().is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Tracks of cue sheets can't be edited",
+                ));
+            }
+            let path = song
+                .get_filepath()
+                .ok_or(io::Error::other("Song has no file"))?;
+            let format = formats::detect(&path)
+                .ok_or(io::Error::new(io::ErrorKind::InvalidData, "Unknown audio format"))?;
+            files.push((path, format));
+        }
+        // Albums are only changed if every file can be written, so they aren't left half edited
+        for (path, format) in &files {
+            formats::check_writable(path, format)?;
+        }
+        for (path, format) in &files {
+            formats::write_tags(path, format, edit)?;
+        }
+        let edited = files.into_iter().map(|f| f.0).collect::<HashSet<_>>();
+        let mut artists = self.artists.lock().unwrap();
+        let songs = artists
+            .iter()
+            .flat_map(|artist| artist.get_albums())
+            .flat_map(|album| album.get_songs())
+            .map(|f| {
+                let mut song = SaveableSong::from_song(f.as_ref());
+                if edited.contains(&song.path) {
+                    song.apply_edit(edit);
+                }
+                song.into_song()
+            })
+            .sorted_by(|a, b| a.path.cmp(&b.path).then(a.start.cmp(&b.start)))
+            .collect();
+        *artists = MusicRepository::group_songs(songs);
+        self.version.fetch_add(1, Ordering::Relaxed);
+        drop(artists);
+        self.cache_artists()
+    }
+    // Restores the files of the last deletion, returns how many were restored. The library is
+    // rescanned afterwards to add them again.
+    pub fn undo_delete(&self) -> Result<usize, io::Error> {
+        let restored = trash::undo()?;
+        self.rescan();
+        Ok(restored)
+    }
+    // Removes the deleted files from the trash for good, returns how many were removed
+    pub fn empty_trash(&self) -> Result<usize, io::Error> {
+        trash::empty()
+    }
+    // Number and size of the deleted files in the trash
+    pub fn trash_usage(&self) -> (usize, u64) {
+        trash::trash_usage()
+    }
+    // Starts looking for songs that are in the library several times, see duplicates::find
+    pub fn find_duplicates(&self) -> DuplicateSearch {
+        DuplicateSearch::start(self.get_albums(), self.fingerprints.clone())
+    }
+    // The songs that are trashed when keeping a duplicate
+    pub fn get_redundant(&self, duplicates: &[Duplicate], keep: usize) -> Vec<Box<dyn Song + Send + Sync>> {
+        duplicates::others(duplicates, keep)
+    }
+    // Keeps a duplicate and moves the others of its group to the trash as a single deletion
+    pub fn keep_duplicate(&mut self, duplicates: &[Duplicate], keep: usize) -> Result<(), io::Error> {
+        let songs = duplicates::others(duplicates, keep);
+        let files = songs.iter().flat_map(|f| f.get_files()).collect_vec();
+        delete_files(&files, &songs)?;
+        self.remove_songs(&songs);
+        Ok(())
+    }
+    // The files that aren't placed as the template describes, without moving anything
+    pub fn plan_organize(&self, template: &str) -> Vec<PlannedMove> {
+        organize::plan(&self.get_albums(), template)
+    }
+    // Moves the files of the plan, returns the moves that were made and the ones that weren't.
+    // The songs get their new paths right away, the library is rescanned afterwards to pick up
+    // the changes of the files.
+    pub fn organize(&mut self, moves: Vec<PlannedMove>) -> (Vec<PlannedMove>, Vec<PlannedMove>) {
+        let (moved, remaining) = organize::apply(moves);
+        if let Some(fingerprints) = &self.fingerprints {
+            for planned in &moved {
+                fingerprints.rename(&planned.from, &planned.to);
+            }
+        }
+        let paths = moved
             .iter()
-            .filter(|album| album.get_name() == song_info.album)
-            .nth(0)
-            .map(|val| val.to_owned())
+            .map(|f| (f.from.to_owned(), f.to.to_owned()))
+            .collect::<HashMap<_, _>>();
+        if !paths.is_empty() {
+            let mut artists = self.artists.lock().unwrap();
+            let songs = artists
+                .iter()
+                .flat_map(|artist| artist.get_albums())
+                .flat_map(|album| album.get_songs())
+                .map(|f| {
+                    let mut song = SaveableSong::from_song(f.as_ref());
+                    if let Some(to) = paths.get(&song.path) {
+                        song.path = to.to_owned();
+                    }
+                    song.into_song()
+                })
+                .sorted_by(|a, b| a.path.cmp(&b.path).then(a.start.cmp(&b.start)))
+                .collect();
+            *artists = MusicRepository::group_songs(songs);
+            self.version.fetch_add(1, Ordering::Relaxed);
+            drop(artists);
+            let _ = self.cache_artists();
+        }
+        self.moved_files.lock().unwrap().extend(paths);
+        self.rescan();
+        (moved, remaining)
+    }
+    // The current version of a song, e.g. after its tags were edited
+    pub fn find_song(&self, song: &dyn Song) -> Option<Box<dyn Song + Send + Sync>> {
+        self.find_song_at(song.get_filepath(), song.get_start())
+    }
+    // The song at a position of a file, the start is None for songs filling their file
+    pub fn find_song_at(
+        &self,
+        path: Option<PathBuf>,
+        start: Option<time::Duration>,
+    ) -> Option<Box<dyn Song + Send + Sync>> {
+        self.get_albums()
+            .iter()
+            .flat_map(|album| album.get_songs())
+            .find(|f| f.get_filepath() == path && f.get_start() == start)
+    }
+    // The album a song is listed in
+    pub fn find_album(&self, song: &dyn Song) -> Option<Box<dyn Album + Send + Sync>> {
+        self.find_album_at(song.get_filepath(), song.get_start())
+    }
+    pub fn find_album_at(
+        &self,
+        path: Option<PathBuf>,
+        start: Option<time::Duration>,
+    ) -> Option<Box<dyn Album + Send + Sync>> {
+        self.get_albums().into_iter().find(|album| {
+            album
+                .get_songs()
+                .iter()
+                .any(|f| f.get_filepath() == path && f.get_start() == start)
+        })
+    }
+    pub fn find_current_album(&self, song_info: &SongInfo) -> Option<Box<dyn Album + Send + Sync>> {
+        // Albums are listed under their album artist, which may differ from the song artist
+        self.artists
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|artist| artist.get_albums())
+            .find(|album| {
+                album.get_name() == song_info.album
+                    && album.get_songs().iter().any(|song| {
+                        song.get_title() == song_info.name && song.get_filepath() == song_info.path
+                    })
+            })
     }
 }
 
@@ -258,6 +588,118 @@ struct SaveableSong {
     number: u16,
     album_name: String,
     release_data: String,
+    #[serde(default)]
+    chapters: Vec<SaveableChapter>,
+    #[serde(default)]
+    start: Option<f64>,
+    #[serde(default)]
+    end: Option<f64>,
+    #[serde(default)]
+    root: Option<PathBuf>,
+    #[serde(default)]
+    artist: Option<String>,
+    #[serde(default)]
+    album_artist: Option<String>,
+    #[serde(default)]
+    disc: Option<u16>,
+    #[serde(default)]
+    disc_total: Option<u16>,
+    #[serde(default)]
+    genre: Option<String>,
+    // Milliseconds since the epoch
+    #[serde(default)]
+    added: Option<i64>,
+}
+
+impl SaveableSong {
+    fn from_song(song: &dyn Song) -> SaveableSong {
+        SaveableSong {
+            path: song.get_filepath().unwrap_or_default(),
+            title: song.get_title(),
+            // Whole seconds, the formatted length leaves out hours
+            length: song.get_length_secs().unwrap_or(0) as f64 * 1000.0,
+            number: song
+                .get_number()
+                .unwrap_or("0".to_string())
+                .parse()
+                .unwrap_or(0),
+            release_data: song.get_release_date().unwrap_or("0".to_string()),
+            album_name: song.get_album_name(),
+            chapters: song
+                .get_chapters()
+                .into_iter()
+                .map(|f| SaveableChapter {
+                    title: f.title,
+                    start: f.start.as_millis() as f64,
+                })
+                .collect(),
+            start: song.get_start().map(|f| f.as_millis() as f64),
+            end: song.get_end().map(|f| f.as_millis() as f64),
+            root: song.get_library_root(),
+            artist: Some(song.get_artist_name()),
+            album_artist: Some(song.get_album_artist_name()),
+            disc: song.get_disc_number(),
+            disc_total: song.get_disc_total(),
+            genre: song.get_genre(),
+            added: song.get_added().map(|f| f.timestamp_millis()),
+        }
+    }
+    fn apply_edit(&mut self, edit: &TagEdit) {
+        if let Some(title) = &edit.title {
+            self.title = title.to_owned();
+        }
+        if let Some(artist) = &edit.artist {
+            self.artist = Some(artist.to_owned());
+        }
+        if let Some(album_artist) = &edit.album_artist {
+            self.album_artist = Some(album_artist.to_owned());
+        }
+        if let Some(album) = &edit.album {
+            self.album_name = album.to_owned();
+        }
+        // Songs without a number or year are stored with 0
+        if let Some(track_number) = edit.track_number {
+            self.number = track_number.unwrap_or(0);
+        }
+        if let Some(disc_number) = edit.disc_number {
+            self.disc = disc_number;
+        }
+        if let Some(year) = edit.year {
+            self.release_data = year.unwrap_or(0).to_string();
+        }
+        if let Some(genre) = &edit.genre {
+            self.genre = Some(genre.to_owned());
+        }
+    }
+    fn into_song(self) -> FsSong {
+        FsSong::fastnew(
+            self.path,
+            self.title,
+            self.length,
+            self.number,
+            self.album_name,
+            self.release_data,
+            self.chapters
+                .into_iter()
+                .map(|f| Chapter {
+                    title: f.title,
+                    start: time::Duration::from_millis(f.start as u64),
+                })
+                .collect(),
+        )
+        .with_offset_millis(self.start, self.end)
+        .with_optional_root(self.root)
+        .with_artists(self.artist, self.album_artist)
+        .with_disc(self.disc, self.disc_total)
+        .with_genre(self.genre)
+        .with_added(self.added)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct SaveableChapter {
+    title: String,
+    start: f64,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -272,10 +714,69 @@ struct SaveableArtist {
     name: String,
 }
 
-pub fn time_to_millis(time: String) -> Option<f64> {
-    let re = Regex::new(r"(\d\d)[:](\d\d)").unwrap();
-    let captures = re.captures(&time)?;
-    Some(captures[1].parse::<f64>().ok()? * 60000.0 + captures[2].parse::<f64>().ok()? * 1000.0)
+// Songs without a year have the release date "0", which FsAlbum leaves out
+// Strips featured artists from an artist name, e.g. "A feat. B" becomes "a"
+fn lead_artist(artist: &str) -> String {
+    let artist = artist.to_lowercase();
+    [" feat. ", " feat ", " ft. ", " featuring "]
+        .iter()
+        .filter_map(|f| artist.find(f))
+        .min()
+        .map_or(artist.as_str(), |i| &artist[..i])
+        .trim()
+        .to_string()
+}
+fn album_year(album: &(dyn Album + Send + Sync)) -> Option<i32> {
+    album.get_release_date().get(..4)?.parse().ok()
+}
+
+// Whether the songs of an album are spread over several discs
+pub fn is_multi_disc(songs: &[Box<dyn Song + Send + Sync>]) -> bool {
+    songs.iter().any(|f| f.get_disc_total().is_some_and(|t| t > 1))
+        || songs.iter().map(|f| f.get_disc_number().unwrap_or(1)).unique().count() > 1
+}
+
+// When the newest song of an album was added
+pub fn album_added(album: &(dyn Album + Send + Sync)) -> Option<DateTime<Local>> {
+    album.get_songs().iter().filter_map(|f| f.get_added()).max()
+}
+
+fn save_artists(artists: &[Box<dyn Artist + Send + Sync>]) -> Result<(), io::Error> {
+    let data = serde_json::to_string(
+        &artists
+            .iter()
+            .map(|f| SaveableArtist {
+                name: f.get_name(),
+                albums: f
+                    .get_albums()
+                    .into_iter()
+                    .map(|f| SaveableAlbum {
+                        name: f.get_name(),
+                        songs: f
+                            .get_songs()
+                            .into_iter()
+                            .filter(|f| f.get_filepath().is_some())
+                            .map(|f| SaveableSong::from_song(f.as_ref()))
+                            .collect(),
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<SaveableArtist>>(),
+    )?;
+
+    let mut file = File::create(cache_path("artist_cache")?)?;
+    let mut content = Cursor::new(data);
+    io::copy(&mut content, &mut file)?;
+    Ok(())
+}
+
+// Path of a file in the cache directory, which is created if necessary
+fn cache_path(name: &str) -> Result<PathBuf, io::Error> {
+    let mut path = cache_dir().ok_or(io::Error::other("Failed to find cache dir"))?;
+    path.push("mplayer");
+    fs::create_dir_all(&path)?;
+    path.push(name);
+    Ok(path)
 }
 
 fn depth_first_search_files(files: Vec<PathBuf>) -> Vec<PathBuf> {
@@ -283,10 +784,12 @@ fn depth_first_search_files(files: Vec<PathBuf>) -> Vec<PathBuf> {
         .into_iter()
         .flat_map(|f| {
             if f.is_dir() {
+                // Unreadable directories, e.g. unmounted network shares, are skipped
                 depth_first_search_files(
                     fs::read_dir(&f)
-                        .unwrap()
-                        .map(|d| d.unwrap().path())
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|d| d.ok().map(|d| d.path()))
                         .collect(),
                 )
             } else {
@@ -323,10 +826,12 @@ impl Artist for FsArtist {
 }
 
 impl Deleteable for FsArtist {
-    fn delete(&self) {
-        for album in &self.albums {
-            album.delete();
-        }
+    fn get_files(&self) -> Vec<PathBuf> {
+        self.albums.iter().flat_map(|f| f.get_files()).collect()
+    }
+    fn delete(&self) -> Result<(), io::Error> {
+        let songs = self.albums.iter().flat_map(|f| f.get_songs()).collect_vec();
+        delete_files(&self.get_files(), &songs)
     }
 }
 
@@ -337,13 +842,14 @@ pub struct FsAlbum {
 
 impl FsAlbum {
     pub fn new(mut songs: Vec<Box<dyn Song + Send + Sync>>) -> FsAlbum {
-        songs.sort_by(|a, b| {
-            a.get_number()
-                .unwrap()
-                .parse::<isize>()
-                .unwrap()
-                .partial_cmp(&b.get_number().unwrap().parse::<isize>().unwrap())
-                .unwrap()
+        // Songs without a disc belong to the first one, ties keep the order of the file names
+        songs.sort_by_key(|f| {
+            (
+                f.get_disc_number().unwrap_or(1),
+                f.get_number()
+                    .and_then(|n| n.parse::<u16>().ok())
+                    .unwrap_or(0),
+            )
         });
         FsAlbum { songs }
     }
@@ -364,7 +870,7 @@ impl Album for FsAlbum {
         self.songs
             .iter()
             .cloned()
-            .map(|f| f.get_artist_name())
+            .map(|f| f.get_album_artist_name())
             .collect::<Vec<String>>()
             .first()
             .unwrap_or(&"".to_string())
@@ -401,10 +907,26 @@ impl Album for FsAlbum {
 }
 
 impl Deleteable for FsAlbum {
-    fn delete(&self) {
-        for song in &self.songs {
-            song.delete();
-        }
+    // Tracks of a cue sheet share their file, which belongs to the album as a whole. The sheet
+    // is removed with it, as its tracks would come back otherwise.
+    fn get_files(&self) -> Vec<PathBuf> {
+        let sheets = self
+            .songs
+            .iter()
+            .filter(|f| f.get_start().is_some())
+            .filter_map(|f| f.get_filepath())
+            .unique()
+            .flat_map(|f| cue::find_cue_sheets(&f))
+            .collect_vec();
+        self.songs
+            .iter()
+            .filter_map(|f| f.get_filepath())
+            .chain(sheets)
+            .unique()
+            .collect()
+    }
+    fn delete(&self) -> Result<(), io::Error> {
+        delete_files(&self.get_files(), &self.songs)
     }
 }
 
@@ -416,27 +938,62 @@ pub struct FsSong {
     number: u16,
     album_name: String,
     release_data: String,
+    chapters: Vec<Chapter>,
+    start: Option<time::Duration>,
+    end: Option<time::Duration>,
+    root: Option<PathBuf>,
+    artist: Option<String>,
+    album_artist: Option<String>,
+    disc: Option<u16>,
+    disc_total: Option<u16>,
+    genre: Option<String>,
+    added: Option<i64>,
 }
 
 impl FsSong {
     pub fn new(path: PathBuf) -> Option<FsSong> {
-        let extension = infer::get_from_path(&path).ok()??.extension();
-        if !(extension == "mp3" || extension == "flac" || extension == "wav" || extension == "m4a")
-        {
-            return None;
-        }
-        let tags = Tag::new().read_from_path(&path).ok()?;
+        let format = formats::detect(&path)?;
+        // Untagged files are still added, using their file and directory names
+        let tags = formats::read_tags(&path, format).unwrap_or_default();
+        let (file_number, file_title) = split_track_number(
+            &path.file_stem().unwrap_or_default().to_string_lossy(),
+        );
+        let directory = path
+            .parent()
+            .and_then(|f| f.file_name())
+            .map(|f| f.to_string_lossy().to_string());
         Some(FsSong {
             path: path.to_owned(),
-            title: tags.title()?.to_string(),
-            length: mp3_duration::from_path(&path)
-                .unwrap_or(std::time::Duration::from_secs(0))
+            title: tags.title.filter(|f| !f.is_empty()).unwrap_or(file_title),
+            length: duration::read_duration(&path, format.extension)
+                .unwrap_or_default()
                 .as_millis() as f64,
-            number: tags.track_number()?,
-            album_name: tags.album_title().unwrap_or("").to_string(),
-            release_data: if tags.date().is_none() {"0".to_string()} else {tags.date().unwrap().year.to_string()},
+            // Songs without a number are ordered by their file name within the album
+            number: tags.track_number.or(file_number).unwrap_or(0),
+            album_name: tags
+                .album
+                .filter(|f| !f.is_empty())
+                .or(directory)
+                .unwrap_or_default(),
+            release_data: tags.year.map_or("0".to_string(), |f| f.to_string()),
+            chapters: chapters::read_chapters(&path, format.extension),
+            start: None,
+            end: None,
+            root: None,
+            artist: tags.artist,
+            album_artist: tags
+                .album_artist
+                .or(tags.compilation.then(|| VARIOUS_ARTISTS.to_string())),
+            disc: tags.disc_number,
+            disc_total: tags.disc_total,
+            genre: tags.genre.filter(|f| !f.is_empty()),
+            added: None,
         })
     }
+    // Type of an audio file that can be read, None for other files
+    pub fn audio_extension(path: &Path) -> Option<&'static str> {
+        formats::detect(path).map(|f| f.extension)
+    }
     pub fn fastnew(
         path: PathBuf,
         title: String,
@@ -444,6 +1001,7 @@ impl FsSong {
         number: u16,
         album_name: String,
         release_data: String,
+        chapters: Vec<Chapter>,
     ) -> FsSong {
         FsSong {
             path,
@@ -452,10 +1010,93 @@ impl FsSong {
             number,
             album_name,
             release_data,
+            chapters,
+            start: None,
+            end: None,
+            root: None,
+            artist: None,
+            album_artist: None,
+            disc: None,
+            disc_total: None,
+            genre: None,
+            added: None,
+        }
+    }
+    pub fn with_artists(mut self, artist: Option<String>, album_artist: Option<String>) -> FsSong {
+        self.artist = artist.filter(|f| !f.is_empty());
+        self.album_artist = album_artist.filter(|f| !f.is_empty());
+        self
+    }
+    pub fn with_disc(mut self, disc: Option<u16>, total: Option<u16>) -> FsSong {
+        self.disc = disc;
+        self.disc_total = total;
+        self
+    }
+    pub fn with_genre(mut self, genre: Option<String>) -> FsSong {
+        self.genre = genre;
+        self
+    }
+    // Milliseconds since the epoch when the file was first found
+    pub fn with_added(mut self, added: Option<i64>) -> FsSong {
+        self.added = added;
+        self
+    }
+    // The artist directory for files laid out as Artist/Album/track inside of the library root
+    fn directory_artist_name(&self) -> String {
+        let artist_dir = self.path.parent().and_then(|f| f.parent());
+        let inside_root = match (&self.root, artist_dir) {
+            (Some(root), Some(dir)) => dir.starts_with(root) && dir != root,
+            (None, dir) => dir.is_some(),
+            _ => false,
+        };
+        artist_dir
+            .filter(|_| inside_root)
+            .and_then(|f| f.file_name())
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or("Unknown Artist".to_string())
+    }
+    // Remembers the library root the song was found in
+    pub fn with_root(mut self, root: PathBuf) -> FsSong {
+        self.root = Some(root);
+        self
+    }
+    fn with_optional_root(mut self, root: Option<PathBuf>) -> FsSong {
+        self.root = root;
+        self
+    }
+    // Restricts the song to a part of its file
+    pub fn with_offset(mut self, start: time::Duration, end: Option<time::Duration>) -> FsSong {
+        self.start = Some(start);
+        self.end = end;
+        self
+    }
+    fn with_offset_millis(self, start: Option<f64>, end: Option<f64>) -> FsSong {
+        match start {
+            Some(start) => self.with_offset(
+                time::Duration::from_millis(start as u64),
+                end.map(|f| time::Duration::from_millis(f as u64)),
+            ),
+            None => self,
         }
     }
 }
 
+// Splits a file name like "03 - Title" or "03. Title" into the track number and the title
+fn split_track_number(file_name: &str) -> (Option<u16>, String) {
+    let title = file_name.trim_start_matches(|c: char| c.is_ascii_digit());
+    let number = file_name[..file_name.len() - title.len()].parse().ok();
+    let title = title
+        .trim_start()
+        .strip_prefix('-')
+        .or_else(|| title.strip_prefix('.'))
+        .map(|f| f.trim())
+        .filter(|f| !f.is_empty());
+    match (number, title) {
+        (Some(number), Some(title)) => (Some(number), title.to_string()),
+        _ => (None, file_name.to_string()),
+    }
+}
+
 impl Song for FsSong {
     fn get_title(&self) -> String {
         self.title.to_owned()
@@ -478,22 +1119,31 @@ impl Song for FsSong {
     }
 
     fn get_artist_name(&self) -> String {
-        self.path
-            .parent()
-            .unwrap()
-            .parent()
-            .unwrap()
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
+        self.artist
+            .to_owned()
+            .or(self.album_artist.to_owned())
+            .unwrap_or_else(|| self.directory_artist_name())
+    }
+
+    fn get_album_artist_name(&self) -> String {
+        self.album_artist
+            .to_owned()
+            .or(self.artist.to_owned())
+            .unwrap_or_else(|| self.directory_artist_name())
     }
 
     fn get_number(&self) -> Option<String> {
         Some(self.number.to_string())
     }
 
+    fn get_disc_number(&self) -> Option<u16> {
+        self.disc
+    }
+
+    fn get_disc_total(&self) -> Option<u16> {
+        self.disc_total
+    }
+
     fn is_local(&self) -> bool {
         true
     }
@@ -513,10 +1163,133 @@ impl Song for FsSong {
             Some(self.release_data.to_owned())
         }
     }
+
+    fn get_genre(&self) -> Option<String> {
+        self.genre.to_owned()
+    }
+
+    fn get_chapters(&self) -> Vec<Chapter> {
+        self.chapters.to_owned()
+    }
+
+    fn get_start(&self) -> Option<time::Duration> {
+        self.start
+    }
+
+    fn get_end(&self) -> Option<time::Duration> {
+        self.end
+    }
+
+    fn get_library_root(&self) -> Option<PathBuf> {
+        self.root.to_owned()
+    }
+
+    fn get_added(&self) -> Option<DateTime<Local>> {
+        Local.timestamp_millis_opt(self.added?).single()
+    }
 }
 
 impl Deleteable for FsSong {
-    fn delete(&self) {
-        let _ = fs::remove_file(&self.path);
+    // The file of a cue sheet track also contains the other tracks, so it is kept
+    fn get_files(&self) -> Vec<PathBuf> {
+        match self.start {
+            Some(_) => vec![],
+            None => vec![self.path.to_owned()],
+        }
+    }
+    fn delete(&self) -> Result<(), io::Error> {
+        // Nothing could be deleted, and the track would come back with the next scan
+        if self.start.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "The song is part of a disc image, delete the album instead",
+            ));
+        }
+        let song = Box::new(self.to_owned()) as Box<dyn Song + Send + Sync>;
+        delete_files(&self.get_files(), &[song])
+    }
+}
+
+// Moves the files to the trash and removes the directories left empty, up to the library root
+// of the songs in them
+fn delete_files(files: &[PathBuf], songs: &[Box<dyn Song + Send + Sync>]) -> Result<(), io::Error> {
+    trash::trash(files)?;
+    let roots = songs
+        .iter()
+        .filter_map(|f| Some((f.get_filepath()?, f.get_library_root()?)))
+        .collect::<HashMap<_, _>>();
+    for file in files {
+        if let Some(root) = roots.get(file) {
+            organize::remove_empty_dirs(file.parent(), root);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(number: u16, artist: &str, album_artist: Option<&str>) -> FsSong {
+        FsSong::fastnew(
+            PathBuf::from(format!("/music/Album/{number}.mp3")),
+            number.to_string(),
+            0.0,
+            number,
+            "Album".to_string(),
+            "".to_string(),
+            vec![],
+        )
+        .with_artists(Some(artist.to_string()), album_artist.map(|f| f.to_string()))
+    }
+
+    fn album_artists(songs: Vec<FsSong>) -> Vec<String> {
+        MusicRepository::detect_compilations(songs)
+            .iter()
+            .map(|f| f.get_album_artist_name())
+            .collect()
+    }
+
+    #[test]
+    fn guest_artists_are_no_compilation() {
+        let songs = vec![song(1, "A", None), song(2, "A feat. B", None), song(3, "A feat. C", None)];
+        assert_eq!(album_artists(songs), vec!["A", "A feat. B", "A feat. C"]);
+        let songs = vec![song(1, "A", Some("A")), song(2, "B", Some("A")), song(3, "C", Some("A"))];
+        assert_eq!(album_artists(songs), vec!["A"; 3]);
+    }
+
+    #[test]
+    fn shared_album_artist_is_compilation() {
+        let songs = vec![
+            song(1, "A", Some("Various")),
+            song(2, "B", Some("Various")),
+            song(3, "C", Some("Various")),
+        ];
+        assert_eq!(album_artists(songs), vec![VARIOUS_ARTISTS; 3]);
+        let songs = vec![song(1, "A", None), song(2, "B", None), song(3, "C feat. A", None)];
+        assert_eq!(album_artists(songs), vec![VARIOUS_ARTISTS; 3]);
+    }
+
+    #[test]
+    fn track_numbers() {
+        assert_eq!(split_track_number("03 - Title"), (Some(3), "Title".to_string()));
+        assert_eq!(split_track_number("03. Title"), (Some(3), "Title".to_string()));
+        assert_eq!(split_track_number("12 -  A - B "), (Some(12), "A - B".to_string()));
+    }
+
+    #[test]
+    fn malformed_track_numbers() {
+        assert_eq!(split_track_number("Title"), (None, "Title".to_string()));
+        assert_eq!(split_track_number("03"), (None, "03".to_string()));
+        assert_eq!(split_track_number("03 - "), (None, "03 - ".to_string()));
+        assert_eq!(split_track_number("03 Title"), (None, "03 Title".to_string()));
+        assert_eq!(split_track_number("99999 - Title"), (None, "99999 - Title".to_string()));
+        assert_eq!(split_track_number(""), (None, "".to_string()));
+    }
+
+    #[test]
+    fn differing_album_artists_are_no_compilation() {
+        let songs = vec![song(1, "A", Some("A")), song(2, "B", Some("B")), song(3, "C", None)];
+        assert_eq!(album_artists(songs), vec!["A", "B", "C"]);
     }
 }
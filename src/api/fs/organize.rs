@@ -0,0 +1,187 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::is_multi_disc;
+use crate::api::{Album, Song};
+
+// A file that is moved to its place in the library layout
+#[derive(Clone)]
+pub struct PlannedMove {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    // The library directory of the file, directories above it are never removed
+    pub root: PathBuf,
+    // Why the file isn't moved, e.g. because the destination already exists
+    pub conflict: Option<String>,
+}
+
+// Finds the files that aren't where the template puts them. The template is a path relative to
+// the library root, like {albumartist}/{year} - {album}/{disc}{track:02} {title}.{ext}
+pub fn plan(albums: &[Box<dyn Album + Send + Sync>], template: &str) -> Vec<PlannedMove> {
+    let mut moves = vec![];
+    for album in albums {
+        let songs = album.get_songs();
+        let multi_disc = is_multi_disc(&songs);
+        for song in &songs {
+            // Cue sheets reference their file by name, so it has to stay where it is
+            if song.get_start().is_some() {
+                continue;
+            }
+            let (from, root) = match (song.get_filepath(), song.get_library_root()) {
+                (Some(from), Some(root)) => (from, root),
+                _ => continue,
+            };
+            let to = root.join(render(template, song.as_ref(), &from, multi_disc));
+            if to != from {
+                moves.push(PlannedMove {
+                    from,
+                    to,
+                    root,
+                    conflict: None,
+                });
+            }
+        }
+    }
+    let mut targets = HashMap::new();
+    for planned in &moves {
+        *targets.entry(planned.to.to_owned()).or_insert(0) += 1;
+    }
+    for planned in &mut moves {
+        planned.conflict = if targets[&planned.to] > 1 {
+            Some("Several files would be moved here".to_string())
+        } else if planned.to.exists() {
+            Some("The destination already exists".to_string())
+        } else {
+            None
+        };
+    }
+    moves.sort_by(|a, b| a.from.cmp(&b.from));
+    moves
+}
+
+// Moves the files without conflicts and removes the directories left empty. Returns the moves
+// that were made and the ones that weren't, with failed ones getting the error as their conflict.
+pub fn apply(moves: Vec<PlannedMove>) -> (Vec<PlannedMove>, Vec<PlannedMove>) {
+    let mut moved = vec![];
+    let mut remaining = vec![];
+    for mut planned in moves {
+        if planned.conflict.is_none() {
+            match move_file(&planned.from, &planned.to) {
+                Ok(()) => {
+                    remove_empty_dirs(planned.from.parent(), &planned.root);
+                    moved.push(planned);
+                    continue;
+                }
+                Err(e) => planned.conflict = Some(e.to_string()),
+            }
+        }
+        remaining.push(planned);
+    }
+    (moved, remaining)
+}
+
+pub(super) fn move_file(from: &Path, to: &Path) -> Result<(), io::Error> {
+    // Checked again, as the library may have changed since the moves were planned
+    if to.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "The destination already exists",
+        ));
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Renaming doesn't work across file systems
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+pub(super) fn remove_empty_dirs(dir: Option<&Path>, root: &Path) {
+    let mut dir = dir;
+    while let Some(d) = dir.filter(|d| d.starts_with(root) && *d != root) {
+        // Fails for directories that aren't empty
+        if fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
+fn render(template: &str, song: &dyn Song, path: &Path, multi_disc: bool) -> PathBuf {
+    template
+        .split('/')
+        .map(|component| {
+            let mut name = String::new();
+            let mut rest = component;
+            while let Some(start) = rest.find('{') {
+                let length = match rest[start..].find('}') {
+                    Some(l) => l,
+                    None => break,
+                };
+                name.push_str(&rest[..start]);
+                name.push_str(&placeholder(
+                    &rest[start + 1..start + length],
+                    song,
+                    path,
+                    multi_disc,
+                ));
+                rest = &rest[start + length + 1..];
+            }
+            name.push_str(rest);
+            sanitize(&name)
+        })
+        .collect()
+}
+
+// The value of a placeholder like {title} or {track:02}, where 02 pads numbers with zeros
+fn placeholder(key: &str, song: &dyn Song, path: &Path, multi_disc: bool) -> String {
+    let (name, width) = match key.split_once(':') {
+        Some((name, format)) => (name, format.parse().unwrap_or(0)),
+        None => (key, 0),
+    };
+    let number = |n: Option<String>| match n.and_then(|f| f.parse::<u32>().ok()) {
+        Some(n) => format!("{:0width$}", n, width = width),
+        None => String::new(),
+    };
+    match name {
+        "albumartist" => song.get_album_artist_name(),
+        "artist" => song.get_artist_name(),
+        "album" => song.get_album_name(),
+        "title" => song.get_title(),
+        "genre" => song.get_genre().unwrap_or_default(),
+        "year" => song.get_release_date().unwrap_or_default(),
+        "track" => number(song.get_number()),
+        // Only albums with several discs get their disc number
+        "disc" if multi_disc => number(Some(song.get_disc_number().unwrap_or(1).to_string())),
+        "disc" => String::new(),
+        "ext" => path
+            .extension()
+            .map(|f| f.to_string_lossy().to_lowercase())
+            .unwrap_or_default(),
+        _ => format!("{{{}}}", key),
+    }
+}
+
+// Replaces characters that can't be part of a file name on common file systems
+fn sanitize(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    let name = name.trim().trim_end_matches('.');
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name.to_string()
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::HashMap,
+    fs::{self, DirBuilder, File, OpenOptions},
+    io::{self, BufReader, Write},
+    os::unix::fs::{DirBuilderExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use dirs::data_dir;
+use serde::{Deserialize, Serialize};
+
+use super::organize::move_file;
+use crate::api::history::ListenHistory;
+
+// Deleted files are moved to the trash of the desktop, where file managers can restore them.
+// Files on other file systems than the home directory go to the trash at the top of their
+// mount, so they don't have to be copied. The files trashed by mplayer are logged, so the last
+// deletion can be undone and emptying the trash only removes these files.
+
+#[derive(Serialize, Deserialize)]
+struct TrashedFile {
+    original: PathBuf,
+    trashed: PathBuf,
+    info: PathBuf,
+}
+
+// Every deletion is a list of files, e.g. all files of an artist
+#[derive(Default, Serialize, Deserialize)]
+struct TrashLog {
+    deletions: Vec<Vec<TrashedFile>>,
+}
+
+impl TrashLog {
+    // The log is kept with the listening history, as clearing the cache would lose the deletions
+    fn path() -> Result<PathBuf, io::Error> {
+        let path = ListenHistory::data_path()?;
+        fs::create_dir_all(&path)?;
+        Ok(path.join("trash_log"))
+    }
+    fn load() -> TrashLog {
+        TrashLog::path()
+            .and_then(File::open)
+            .ok()
+            .and_then(|f| serde_json::from_reader(BufReader::new(f)).ok())
+            .unwrap_or_default()
+    }
+    // The log is replaced at once, so it isn't lost when writing fails halfway
+    fn save(&self) -> Result<(), io::Error> {
+        let path = TrashLog::path()?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string(self)?)?;
+        fs::rename(tmp_path, path)
+    }
+}
+
+// Moves the files to the trash as a single deletion
+pub fn trash(paths: &[PathBuf]) -> Result<(), io::Error> {
+    // Files of the same file system share their trash
+    let mut dirs = HashMap::<u64, PathBuf>::new();
+    let mut deletion = vec![];
+    let mut result = Ok(());
+    for path in paths {
+        let dir = fs::metadata(path).and_then(|metadata| {
+            if let Some(dir) = dirs.get(&metadata.dev()) {
+                return Ok(dir.to_owned());
+            }
+            let dir = trash_dir(path, metadata.dev())?;
+            fs::create_dir_all(dir.join("files"))?;
+            fs::create_dir_all(dir.join("info"))?;
+            dirs.insert(metadata.dev(), dir.to_owned());
+            Ok(dir)
+        });
+        match dir.and_then(|dir| trash_file(&dir, path)) {
+            Ok(f) => deletion.push(f),
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+    // Files trashed before an error can still be restored
+    if !deletion.is_empty() {
+        let mut log = TrashLog::load();
+        log.deletions.push(deletion);
+        log.save()?;
+    }
+    result
+}
+
+fn trash_file(dir: &Path, path: &Path) -> Result<TrashedFile, io::Error> {
+    let path = path.canonicalize()?;
+    let name = path
+        .file_name()
+        .ok_or(io::Error::other("Path has no file name"))?
+        .to_string_lossy()
+        .to_string();
+    // The info file reserves the name, files with the same name get a number
+    let (info, mut info_file) = (0..)
+        .map(|i| match i {
+            0 => name.to_owned(),
+            i => format!("{}.{}", name, i),
+        })
+        .find_map(|name| {
+            let info = dir.join("info").join(format!("{}.trashinfo", name));
+            let file = OpenOptions::new().write(true).create_new(true).open(&info);
+            match file {
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => None,
+                file => Some(file.map(|f| (info, f))),
+            }
+        })
+        .unwrap()?;
+    let trashed = dir
+        .join("files")
+        .join(info.file_stem().unwrap_or_default());
+    let result = write!(
+        info_file,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(&path),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    )
+    .and_then(|_| move_file(&path, &trashed));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&info);
+        return Err(e);
+    }
+    Ok(TrashedFile {
+        original: path,
+        trashed,
+        info,
+    })
+}
+
+// Moves the files of the last deletion back, returns how many were restored
+pub fn undo() -> Result<usize, io::Error> {
+    let mut log = TrashLog::load();
+    let deletion = match log.deletions.pop() {
+        Some(d) => d,
+        None => return Ok(0),
+    };
+    let mut restored = 0;
+    let mut result = Ok(());
+    for file in &deletion {
+        // Files removed from the trash by other programs are skipped
+        if !file.trashed.exists() {
+            continue;
+        }
+        match move_file(&file.trashed, &file.original) {
+            Ok(()) => {
+                let _ = fs::remove_file(&file.info);
+                restored += 1;
+            }
+            Err(e) => result = Err(e),
+        }
+    }
+    log.save()?;
+    result.map(|_| restored)
+}
+
+// Removes the files trashed by mplayer for good, returns how many were removed
+pub fn empty() -> Result<usize, io::Error> {
+    let log = TrashLog::load();
+    let mut removed = 0;
+    for file in log.deletions.iter().flatten() {
+        if fs::remove_file(&file.trashed).is_ok() {
+            removed += 1;
+        }
+        let _ = fs::remove_file(&file.info);
+    }
+    TrashLog::default().save()?;
+    Ok(removed)
+}
+
+// Number and size of the files trashed by mplayer, which emptying the trash removes
+pub fn trash_usage() -> (usize, u64) {
+    let files = TrashLog::load()
+        .deletions
+        .into_iter()
+        .flatten()
+        .map(|f| f.trashed)
+        .collect::<Vec<_>>();
+    disk_usage(&files)
+}
+
+// Number and size of the existing files
+pub fn disk_usage(paths: &[PathBuf]) -> (usize, u64) {
+    paths
+        .iter()
+        .filter_map(|f| fs::metadata(f).ok())
+        .fold((0, 0), |(count, size), f| (count + 1, size + f.len()))
+}
+
+// The trash of a file as the freedesktop.org trash specification describes: the one in the home
+// directory for files on its file system, otherwise $topdir/.Trash/$uid or $topdir/.Trash-$uid
+// at the top of the mount of the file. Files go to the home trash if neither can be used.
+fn trash_dir(path: &Path, device: u64) -> Result<PathBuf, io::Error> {
+    let home = data_dir()
+        .ok_or(io::Error::other("Failed to find data dir"))?
+        .join("Trash");
+    fs::create_dir_all(&home)?;
+    if fs::metadata(&home)?.dev() == device {
+        return Ok(home);
+    }
+    let top_dir = path
+        .ancestors()
+        .skip(1)
+        .take_while(|f| fs::metadata(f).is_ok_and(|f| f.dev() == device))
+        .last();
+    let top_dir = match top_dir {
+        Some(dir) => dir,
+        None => return Ok(home),
+    };
+    let uid = unsafe { libc::getuid() };
+    // The shared trash has to be a real directory with the sticky bit set
+    let shared = top_dir.join(".Trash");
+    if fs::symlink_metadata(&shared).is_ok_and(|f| f.is_dir() && f.mode() & 0o1000 != 0) {
+        let dir = shared.join(uid.to_string());
+        if DirBuilder::new().mode(0o700).recursive(true).create(&dir).is_ok() {
+            return Ok(dir);
+        }
+    }
+    let dir = top_dir.join(format!(".Trash-{}", uid));
+    let _ = DirBuilder::new().mode(0o700).create(&dir);
+    match fs::symlink_metadata(&dir) {
+        Ok(metadata) if metadata.is_dir() && metadata.uid() == uid => Ok(dir),
+        _ => Ok(home),
+    }
+}
+
+// Paths in trash info files are percent encoded like URLs
+fn encode_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
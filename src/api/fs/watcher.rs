@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    thread,
+};
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+
+// Files are reported once they're written completely, not on every write
+const WATCH_MASK: WatchMask = WatchMask::CREATE
+    .union(WatchMask::CLOSE_WRITE)
+    .union(WatchMask::DELETE)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::MOVED_TO);
+
+pub enum Change {
+    // A file or directory that was added, changed or removed
+    Path(PathBuf),
+    // The system dropped events, so any file may have changed
+    Overflow,
+}
+
+// Watches the library roots and all directories below them, sending every file or directory that
+// was added, changed or removed. inotify doesn't watch recursively by itself, so new directories
+// are added as they appear.
+pub fn watch(roots: &[PathBuf], sender: Sender<Change>) -> Result<(), io::Error> {
+    let mut inotify = Inotify::init()?;
+    let mut watches = inotify.watches();
+    let mut directories = HashMap::new();
+    for root in roots {
+        add_watches(&mut watches, &mut directories, root);
+    }
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            let events = match inotify.read_events_blocking(&mut buffer) {
+                Ok(e) => e,
+                Err(_) => break,
+            };
+            for event in events {
+                if event.mask.contains(EventMask::IGNORED) {
+                    directories.remove(&event.wd);
+                    continue;
+                }
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    if sender.send(Change::Overflow).is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                let path = match (directories.get(&event.wd), event.name) {
+                    (Some(dir), Some(name)) => dir.join(name),
+                    _ => continue,
+                };
+                if event.mask.contains(EventMask::ISDIR) {
+                    if event.mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+                        add_watches(&mut watches, &mut directories, &path);
+                    } else if event.mask.contains(EventMask::MOVED_FROM) {
+                        // Directories keep their watch when moved, which would report wrong paths
+                        remove_watches(&mut watches, &mut directories, &path);
+                    }
+                }
+                if sender.send(Change::Path(path)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+fn add_watches(
+    watches: &mut Watches,
+    directories: &mut HashMap<WatchDescriptor, PathBuf>,
+    dir: &Path,
+) {
+    // Directories beyond the watch limit of the system are left out
+    if let Ok(wd) = watches.add(dir, WATCH_MASK) {
+        directories.insert(wd, dir.to_path_buf());
+    }
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        if entry.file_type().is_ok_and(|f| f.is_dir()) {
+            add_watches(watches, directories, &entry.path());
+        }
+    }
+}
+
+fn remove_watches(
+    watches: &mut Watches,
+    directories: &mut HashMap<WatchDescriptor, PathBuf>,
+    dir: &Path,
+) {
+    let moved = directories
+        .iter()
+        .filter(|(_, path)| path.starts_with(dir))
+        .map(|(wd, _)| wd.to_owned())
+        .collect::<Vec<WatchDescriptor>>();
+    for wd in moved {
+        let _ = watches.remove(wd.to_owned());
+        directories.remove(&wd);
+    }
+}
+
@@ -0,0 +1,248 @@
+mod scrobbler_log;
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local, TimeZone};
+use dirs::data_dir;
+use serde::{Deserialize, Serialize};
+
+use super::{player::SongInfo, scrobbler::Scrobbler, Album, Song};
+pub use scrobbler_log::ScrobblerLog;
+
+// A song that was listened to
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Listen {
+    pub path: Option<PathBuf>,
+    // Start of the song inside of its file, used to tell apart the tracks of a cue sheet
+    pub start: Option<u64>,
+    pub artist: String,
+    pub album: String,
+    pub title: String,
+    #[serde(default)]
+    pub number: Option<String>,
+    // Unix timestamp of the moment the song started playing
+    pub timestamp: i64,
+    // Seconds that were actually played
+    pub duration: usize,
+    pub length: usize,
+}
+
+impl Listen {
+    fn new(song_info: &SongInfo, timestamp: i64, duration: usize) -> Listen {
+        Listen {
+            path: song_info.path.to_owned(),
+            start: song_info.start.map(|f| f.as_millis() as u64),
+            artist: song_info.artist.to_owned(),
+            album: song_info.album.to_owned(),
+            title: song_info.name.to_owned(),
+            number: song_info.number.to_owned(),
+            timestamp,
+            duration,
+            length: song_info.length,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ListenStats {
+    pub play_count: usize,
+    pub last_played: Option<DateTime<Local>>,
+}
+
+type SongKey = (Option<PathBuf>, Option<u64>);
+
+// Stores every finished song in a local log
+#[derive(Clone)]
+pub struct ListenHistory {
+    stats: Arc<Mutex<HashMap<SongKey, ListenStats>>>,
+    // Share of the song that has to be played for a listen, e.g. 0.5 for 50%
+    threshold_ratio: f64,
+    // Songs played longer than this always count as listened
+    threshold_duration: Duration,
+    scrobbler_log: Option<ScrobblerLog>,
+    scrobbler: Option<Scrobbler>,
+}
+
+impl ListenHistory {
+    pub fn new() -> ListenHistory {
+        let history = ListenHistory {
+            stats: Arc::new(Mutex::new(HashMap::new())),
+            threshold_ratio: 0.5,
+            threshold_duration: Duration::from_secs(240),
+            scrobbler_log: None,
+            scrobbler: None,
+        };
+        let _ = history.load();
+        history
+    }
+    pub fn with_threshold(mut self, ratio: f64, duration: Duration) -> ListenHistory {
+        self.threshold_ratio = ratio;
+        self.threshold_duration = duration;
+        self
+    }
+    pub fn with_scrobbler_log(mut self, scrobbler_log: ScrobblerLog) -> ListenHistory {
+        self.scrobbler_log = Some(scrobbler_log);
+        self
+    }
+    pub fn with_scrobbler(mut self, scrobbler: Scrobbler) -> ListenHistory {
+        self.scrobbler = Some(scrobbler);
+        self
+    }
+    // Announces a song that just started playing
+    pub fn now_playing(&self, song_info: &SongInfo) {
+        if let Some(scrobbler) = &self.scrobbler {
+            scrobbler.now_playing(Listen::new(song_info, Local::now().timestamp(), 0));
+        }
+    }
+    // Adds a song to the history if enough of it was played. The scrobbler log gets every song,
+    // marking the ones below the threshold as skipped.
+    pub fn record(&self, song_info: &SongInfo) {
+        let played = match song_info.played_time() {
+            Some(p) => p,
+            None => return,
+        };
+        let mut required = self.threshold_duration.as_secs() as f64;
+        // Songs of unknown length only count after the fixed threshold
+        if song_info.length > 0 {
+            required = required.min(song_info.length as f64 * self.threshold_ratio);
+        }
+        let listened = played as f64 >= required;
+        let timestamp = Local::now().timestamp() - played as i64;
+        if let Some(scrobbler_log) = &self.scrobbler_log {
+            let _ = scrobbler_log.append(song_info, timestamp, listened);
+        }
+        if !listened {
+            return;
+        }
+        let listen = Listen::new(song_info, timestamp, played);
+        self.add_stats(&listen);
+        // Appending waits for the history file to be rewritten, see rename
+        let stats = self.stats.lock().unwrap();
+        let _ = ListenHistory::append(&listen);
+        drop(stats);
+        if let Some(scrobbler) = &self.scrobbler {
+            scrobbler.scrobble(listen);
+        }
+    }
+    pub fn get_stats(&self, song: &dyn Song) -> ListenStats {
+        let key = (
+            song.get_filepath(),
+            song.get_start().map(|f| f.as_millis() as u64),
+        );
+        self.stats
+            .lock()
+            .unwrap()
+            .get(&key)
+            .cloned()
+            .unwrap_or_default()
+    }
+    // Puts the most played songs first, songs played equally often keep their order
+    pub fn sort_songs(&self, songs: &mut [Box<dyn Song + Send + Sync>]) {
+        songs.sort_by_cached_key(|f| Reverse(self.get_stats(f.as_ref()).play_count));
+    }
+    // Puts the albums whose songs were played most often first
+    pub fn sort_albums(&self, albums: &mut [Box<dyn Album + Send + Sync>]) {
+        albums.sort_by_cached_key(|album| {
+            Reverse(
+                album
+                    .get_songs()
+                    .iter()
+                    .map(|f| self.get_stats(f.as_ref()).play_count)
+                    .sum::<usize>(),
+            )
+        });
+    }
+    // Moves the listens of files that were moved to their new path, in the stats and in the
+    // history file
+    pub fn rename(&self, moved: &[(PathBuf, PathBuf)]) -> Result<(), io::Error> {
+        if moved.is_empty() {
+            return Ok(());
+        }
+        let moved = moved.iter().cloned().collect::<HashMap<_, _>>();
+        let mut stats = self.stats.lock().unwrap();
+        let renamed = stats
+            .keys()
+            .filter(|(path, _)| path.as_ref().is_some_and(|f| moved.contains_key(f)))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in renamed {
+            if let Some(entry) = stats.remove(&key) {
+                let to = key.0.as_ref().and_then(|f| moved.get(f)).cloned();
+                stats.insert((to, key.1), entry);
+            }
+        }
+
+        // The stats stay locked while the file is rewritten, so no listen is appended meanwhile
+        let path = ListenHistory::history_path()?;
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut contents = String::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            // Lines that can't be read are kept as they are
+            match serde_json::from_str::<Listen>(&line) {
+                Ok(mut listen) if listen.path.as_ref().is_some_and(|f| moved.contains_key(f)) => {
+                    listen.path = listen.path.and_then(|f| moved.get(&f).cloned());
+                    contents.push_str(&serde_json::to_string(&listen)?);
+                }
+                _ => contents.push_str(&line),
+            }
+            contents.push('\n');
+        }
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, contents)?;
+        fs::rename(tmp_path, path)?;
+        drop(stats);
+        Ok(())
+    }
+    fn add_stats(&self, listen: &Listen) {
+        let mut stats = self.stats.lock().unwrap();
+        let entry = stats
+            .entry((listen.path.to_owned(), listen.start))
+            .or_default();
+        let played_at = Local.timestamp_opt(listen.timestamp, 0).single();
+        entry.play_count += 1;
+        if entry.last_played < played_at {
+            entry.last_played = played_at;
+        }
+    }
+    fn load(&self) -> Result<(), io::Error> {
+        let file = File::open(ListenHistory::history_path()?)?;
+        for line in BufReader::new(file).lines() {
+            if let Ok(listen) = serde_json::from_str::<Listen>(&line?) {
+                self.add_stats(&listen);
+            }
+        }
+        Ok(())
+    }
+    fn append(listen: &Listen) -> Result<(), io::Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(ListenHistory::history_path()?)?;
+        writeln!(file, "{}", serde_json::to_string(listen)?)
+    }
+    fn history_path() -> Result<PathBuf, io::Error> {
+        let mut path = ListenHistory::data_path()?;
+        fs::create_dir_all(&path)?;
+        path.push("history");
+        Ok(path)
+    }
+    // Directory containing the history and the default scrobbler log
+    pub fn data_path() -> Result<PathBuf, io::Error> {
+        let mut path = data_dir().ok_or(io::Error::other("Failed to find data dir"))?;
+        path.push("mplayer");
+        Ok(path)
+    }
+}
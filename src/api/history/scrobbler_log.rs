@@ -0,0 +1,57 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::api::player::SongInfo;
+
+// Writes finished songs in the Audioscrobbler format used by Rockbox, which can be submitted
+// later by external tools
+#[derive(Clone)]
+pub struct ScrobblerLog {
+    path: PathBuf,
+}
+
+impl ScrobblerLog {
+    pub fn new(path: PathBuf) -> ScrobblerLog {
+        ScrobblerLog { path }
+    }
+    // Appends a song, `listened` decides whether it's rated as listened (L) or skipped (S)
+    pub fn append(
+        &self,
+        song_info: &SongInfo,
+        timestamp: i64,
+        listened: bool,
+    ) -> Result<(), io::Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        // New logs start with a header
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "#AUDIOSCROBBLER/1.1")?;
+            writeln!(file, "#TZ/UTC")?;
+            writeln!(file, "#CLIENT/mplayer {}", env!("CARGO_PKG_VERSION"))?;
+        }
+        writeln!(
+            file,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t",
+            sanitize(&song_info.artist),
+            sanitize(&song_info.album),
+            sanitize(&song_info.name),
+            song_info.number.to_owned().unwrap_or_default(),
+            song_info.length,
+            if listened { "L" } else { "S" },
+            timestamp,
+        )
+    }
+}
+
+// Tabs and line breaks would break the columns of the log
+fn sanitize(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
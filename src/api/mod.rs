@@ -1,7 +1,10 @@
 pub mod fs;
+pub mod history;
 pub mod player;
-use std::path::PathBuf;
+pub mod scrobbler;
+use std::{io, path::PathBuf, time::Duration};
 
+use chrono::{DateTime, Local};
 use dyn_clone::DynClone;
 
 pub trait Artist: DynClone + Deleteable {
@@ -18,15 +21,37 @@ pub trait Song: DynClone + Send + Deleteable {
     fn get_length_secs(&self) -> Option<usize>;
     fn get_disambiguation(&self) -> Option<String>;
     fn get_artist_name(&self) -> String;
+    // The artist the album of the song is listed under
+    fn get_album_artist_name(&self) -> String;
     fn get_number(&self) -> Option<String>;
+    // Disc of a release spread over several discs
+    fn get_disc_number(&self) -> Option<u16>;
+    fn get_disc_total(&self) -> Option<u16>;
     fn is_local(&self) -> bool;
     fn get_filepath(&self) -> Option<PathBuf>;
     fn get_album_name(&self) -> String;
     fn get_release_date(&self) -> Option<String>;
+    fn get_genre(&self) -> Option<String>;
+    fn get_chapters(&self) -> Vec<Chapter>;
+    // Position inside of the file where the song starts, e.g. for tracks of a cue sheet
+    fn get_start(&self) -> Option<Duration>;
+    // Position inside of the file where the song ends, None if it plays to the end of the file
+    fn get_end(&self) -> Option<Duration>;
+    // The library directory the song was found in
+    fn get_library_root(&self) -> Option<PathBuf>;
+    // When the file of the song was first found in the library
+    fn get_added(&self) -> Option<DateTime<Local>>;
 }
 
 dyn_clone::clone_trait_object!(Song);
 
+// A named position inside of a song, e.g. a chapter of an audiobook
+#[derive(Clone)]
+pub struct Chapter {
+    pub title: String,
+    pub start: Duration,
+}
+
 pub trait Album: DynClone + Send + Deleteable {
     fn get_name(&self) -> String;
     fn get_artist_name(&self) -> String;
@@ -40,7 +65,10 @@ pub trait Album: DynClone + Send + Deleteable {
 dyn_clone::clone_trait_object!(Album);
 
 pub trait Deleteable: DynClone {
-    fn delete(&self);
+    // The files that deleting removes
+    fn get_files(&self) -> Vec<PathBuf>;
+    // Moves the files to the trash
+    fn delete(&self) -> Result<(), io::Error>;
 }
 
 dyn_clone::clone_trait_object!(Deleteable);
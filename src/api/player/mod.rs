@@ -1,8 +1,10 @@
 use std::{
     collections::VecDeque,
     fs::File,
-    io::BufReader,
+    io::{self, BufReader},
+    mem,
     ops::Deref,
+    path::PathBuf,
     sync::{
         mpsc::{self, Receiver, Sender},
         Arc, Mutex,
@@ -11,17 +13,21 @@ use std::{
     time::{Duration, Instant},
 };
 
-use rodio::{Decoder, OutputStream, Sink};
+use rodio::{Decoder, OutputStream, Sink, Source};
 
-use super::{Album, Song};
+use super::{history::ListenHistory, Album, Chapter, Song};
+
+// The decoded audio of a song
+type SongSource = Box<dyn Source<Item = i16> + Send>;
 
 // This struct represents all possible interactions with the music player
 pub enum MusicPlayerEvent {
     Stop,
-    Play(Box<(Decoder<BufReader<File>>, SongInfo)>),
+    Play(Box<(SongSource, SongInfo)>),
     Skip,
     Pause,
     Volume(f32),
+    Seek(Duration),
     None,
 }
 
@@ -32,6 +38,10 @@ pub struct SongInfo {
     pub artist: String,
     pub album: String,
     pub length: usize,
+    pub number: Option<String>,
+    pub chapters: Vec<Chapter>,
+    pub path: Option<PathBuf>,
+    pub start: Option<Duration>,
     play_start: Option<Instant>,
     paused_at: Option<Instant>,
 }
@@ -43,10 +53,28 @@ impl SongInfo {
             artist,
             album,
             length,
+            number: None,
+            chapters: vec![],
+            path: None,
+            start: None,
             play_start: None,
             paused_at: None,
         }
     }
+    pub fn from_song(song: &dyn Song) -> SongInfo {
+        SongInfo {
+            number: song.get_number(),
+            chapters: song.get_chapters(),
+            path: song.get_filepath(),
+            start: song.get_start(),
+            ..SongInfo::new(
+                song.get_title(),
+                song.get_artist_name(),
+                song.get_album_name(),
+                song.get_length_secs().unwrap(),
+            )
+        }
+    }
     pub fn set_start(mut self, start: Instant) -> SongInfo {
         self.play_start = Some(start);
         self
@@ -74,6 +102,19 @@ impl SongInfo {
         }
         self
     }
+    pub fn seeked(mut self, position: Duration) -> SongInfo {
+        let now = Instant::now();
+        self.play_start = now.checked_sub(position).or(Some(now));
+        if self.paused_at.is_some() {
+            self.paused_at = Some(now);
+        }
+        self
+    }
+    // Index of the chapter containing the current play position
+    pub fn current_chapter(&self) -> Option<usize> {
+        let played = Duration::from_secs(self.played_time()? as u64);
+        self.chapters.iter().rposition(|f| f.start <= played)
+    }
 }
 
 #[derive(Clone)]
@@ -83,24 +124,27 @@ pub struct MusicPlayer {
     sender: Sender<MusicPlayerEvent>,
     current_song: Arc<Mutex<Option<SongInfo>>>,
     queue: Arc<Mutex<VecDeque<SongInfo>>>,
+    history: ListenHistory,
 }
 
 impl MusicPlayer {
-    pub fn new() -> MusicPlayer {
+    pub fn new(history: ListenHistory) -> MusicPlayer {
         let (tx, rx) = mpsc::channel::<MusicPlayerEvent>();
         let played_song = Arc::new(Mutex::new(None));
         let queue = Arc::new(Mutex::new(VecDeque::new()));
-        MusicPlayer::start(rx, played_song.to_owned(), queue.to_owned());
+        MusicPlayer::start(rx, played_song.to_owned(), queue.to_owned(), history.to_owned());
         MusicPlayer {
             sender: tx,
             current_song: played_song,
             queue,
+            history,
         }
     }
     fn start(
         rx: Receiver<MusicPlayerEvent>,
         current_song: Arc<Mutex<Option<SongInfo>>>,
         queue: Arc<Mutex<VecDeque<SongInfo>>>,
+        history: ListenHistory,
     ) {
         thread::spawn(move || {
             let (_stream, stream_handle) = OutputStream::try_default().unwrap();
@@ -112,8 +156,11 @@ impl MusicPlayer {
                         sink.play();
                         if sink.empty() {
                             sink.append(source.0);
-                            let mut guard = current_song.lock().unwrap();
-                            *guard = Some(source.1.set_start(Instant::now()));
+                            MusicPlayer::change_song(
+                                &current_song,
+                                Some(source.1.set_start(Instant::now())),
+                                &history,
+                            );
                         } else {
                             queue.lock().unwrap().push_back(source.1.to_owned());
                             internal_queue.push_back(source);
@@ -122,16 +169,18 @@ impl MusicPlayer {
                     MusicPlayerEvent::Stop => {
                         sink.stop();
                         internal_queue.clear();
-                        let mut guard = current_song.lock().unwrap();
-                        *guard = None;
+                        MusicPlayer::change_song(&current_song, None, &history);
                     }
                     MusicPlayerEvent::Skip => {
                         if !sink.empty() && !internal_queue.is_empty() {
                             sink.stop();
                             let song = internal_queue.pop_front().unwrap();
                             sink.append(song.0);
-                            let mut guard = current_song.lock().unwrap();
-                            *guard = Some(song.1.set_start(Instant::now()));
+                            MusicPlayer::change_song(
+                                &current_song,
+                                Some(song.1.set_start(Instant::now())),
+                                &history,
+                            );
                             queue.lock().unwrap().pop_front();
                         }
                     }
@@ -155,62 +204,110 @@ impl MusicPlayer {
                             sink.set_volume(sink.volume() + v);
                         }
                     }
+                    MusicPlayerEvent::Seek(position) => {
+                        if !sink.empty() && sink.try_seek(position).is_ok() {
+                            let mut guard = current_song.lock().unwrap();
+                            *guard = guard.to_owned().map(|f| f.seeked(position));
+                        }
+                    }
                     MusicPlayerEvent::None => {}
                 }
                 // Event for playing a new song after the last is finished
                 if !internal_queue.is_empty() && sink.empty() {
                     let song = internal_queue.pop_front().unwrap();
                     sink.append(song.0);
-                    let mut guard = current_song.lock().unwrap();
-                    *guard = Some(song.1.set_start(Instant::now()));
+                    MusicPlayer::change_song(
+                        &current_song,
+                        Some(song.1.set_start(Instant::now())),
+                        &history,
+                    );
                     queue.lock().unwrap().pop_front();
                 } else if sink.empty() && current_song.lock().unwrap().is_some() {
-                    let mut guard = current_song.lock().unwrap();
-                    *guard = None;
+                    MusicPlayer::change_song(&current_song, None, &history);
                 }
                 thread::sleep(Duration::from_millis(50))
             }
         });
     }
+    // Replaces the current song and reports both songs to the listening history
+    fn change_song(
+        current_song: &Mutex<Option<SongInfo>>,
+        song: Option<SongInfo>,
+        history: &ListenHistory,
+    ) {
+        let announced = song.to_owned();
+        // Writing the history takes a while, so the song is replaced before
+        let finished = mem::replace(&mut *current_song.lock().unwrap(), song);
+        if let Some(finished) = finished {
+            history.record(&finished);
+        }
+        if let Some(song) = &announced {
+            history.now_playing(song);
+        }
+    }
+    // Decodes the file of a song and cuts it to the part belonging to the song. Fails for files
+    // that were moved or deleted and for codecs without a decoder.
+    fn load_source(song: &dyn Song) -> Result<SongSource, io::Error> {
+        let path = song
+            .get_filepath()
+            .ok_or(io::Error::other(format!("{} has no file", song.get_title())))?;
+        let file = File::open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let mut source = Decoder::new(BufReader::new(file)).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported codec in {}: {}", path.display(), e),
+            )
+        })?;
+        Ok(match song.get_start() {
+            Some(start) => {
+                // Decoders which can't seek have to decode everything up to the start
+                let source: SongSource = if source.try_seek(start).is_ok() {
+                    Box::new(source)
+                } else {
+                    Box::new(source.skip_duration(start))
+                };
+                match song.get_end() {
+                    Some(end) => Box::new(source.take_duration(end.saturating_sub(start))),
+                    None => source,
+                }
+            }
+            None => Box::new(source),
+        })
+    }
     // Emptys queue, plays song
-    pub fn play_song(&self, song: Box<dyn Song>, stop_playback: bool) {
-        let file = BufReader::new(File::open(song.get_filepath().unwrap()).unwrap());
-        let source = Decoder::new(file).unwrap();
+    pub fn play_song(&self, song: Box<dyn Song>, stop_playback: bool) -> Result<(), io::Error> {
+        let source = MusicPlayer::load_source(song.as_ref())?;
         if stop_playback {
             self.stop();
         }
         self.sender
             .send(MusicPlayerEvent::Play(Box::new((
                 source,
-                SongInfo::new(
-                    song.get_title(),
-                    song.get_artist_name(),
-                    song.get_album_name(),
-                    song.get_length_secs().unwrap(),
-                ),
+                SongInfo::from_song(song.as_ref()),
             ))))
             .unwrap();
+        Ok(())
     }
-    // Emptys queue, enqueues album
-    pub fn play_album(&self, album: Box<dyn Album>, stop_playback: bool) {
+    // Emptys queue, enqueues album. Nothing is played if a song can't be loaded.
+    pub fn play_album(&self, album: Box<dyn Album>, stop_playback: bool) -> Result<(), io::Error> {
+        let sources = album
+            .get_songs()
+            .iter()
+            .map(|song| {
+                let source = MusicPlayer::load_source(song.as_ref())?;
+                Ok((source, SongInfo::from_song(song.as_ref())))
+            })
+            .collect::<Result<Vec<_>, io::Error>>()?;
         if stop_playback {
             self.stop();
         }
-        for song in album.get_songs() {
-            let file = BufReader::new(File::open(song.get_filepath().unwrap()).unwrap());
-            let source = Decoder::new(file).unwrap();
+        for source in sources {
             self.sender
-                .send(MusicPlayerEvent::Play(Box::new((
-                    source,
-                    SongInfo::new(
-                        song.get_title(),
-                        song.get_artist_name(),
-                        song.get_album_name(),
-                        song.get_length_secs().unwrap(),
-                    ),
-                ))))
+                .send(MusicPlayerEvent::Play(Box::new(source)))
                 .unwrap();
         }
+        Ok(())
     }
     // Pauses if playing, continues if paused
     pub fn pause(&self) {
@@ -224,6 +321,47 @@ impl MusicPlayer {
     pub fn stop(&self) {
         self.sender.send(MusicPlayerEvent::Stop).unwrap();
     }
+    // Jumps to a position of the current song
+    pub fn seek(&self, position: Duration) {
+        self.sender.send(MusicPlayerEvent::Seek(position)).unwrap();
+    }
+    // Jumps to the start of the given chapter of the current song
+    pub fn jump_to_chapter(&self, index: usize) {
+        if let Some(chapter) = self
+            .get_song_info()
+            .and_then(|f| f.chapters.get(index).cloned())
+        {
+            self.seek(chapter.start);
+        }
+    }
+    pub fn next_chapter(&self) {
+        if let Some(song_info) = self.get_song_info() {
+            match song_info.current_chapter() {
+                Some(i) => self.jump_to_chapter(i + 1),
+                None => self.jump_to_chapter(0),
+            }
+        }
+    }
+    // Restarts the current chapter, or goes to the previous one if the current just started
+    pub fn previous_chapter(&self) {
+        if let Some(song_info) = self.get_song_info() {
+            if let Some(i) = song_info.current_chapter() {
+                let played = song_info.played_time().unwrap_or(0) as u64;
+                if played < song_info.chapters[i].start.as_secs() + 3 && i > 0 {
+                    self.jump_to_chapter(i - 1);
+                } else {
+                    self.jump_to_chapter(i);
+                }
+            }
+        }
+    }
+    // Reports the current song to the listening history before quitting
+    pub fn finish(&self) {
+        let finished = self.current_song.lock().unwrap().take();
+        if let Some(finished) = finished {
+            self.history.record(&finished);
+        }
+    }
     pub fn get_song_info(&self) -> Option<SongInfo> {
         self.current_song.lock().unwrap().deref().to_owned()
     }
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use super::ScrobbleService;
+use crate::api::history::Listen;
+
+// Submits listens to the Last.fm API or any server implementing it, e.g. Libre.fm
+pub struct LastFm {
+    url: String,
+    api_key: String,
+    api_secret: String,
+    session_key: String,
+    agent: ureq::Agent,
+}
+
+impl LastFm {
+    pub fn new(url: String, api_key: String, api_secret: String, session_key: String) -> LastFm {
+        LastFm {
+            url,
+            api_key,
+            api_secret,
+            session_key,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(10))
+                .build(),
+        }
+    }
+    fn send(&self, method: &str, mut params: Vec<(String, String)>) -> Result<(), Box<ureq::Error>> {
+        params.push(("method".to_string(), method.to_string()));
+        params.push(("api_key".to_string(), self.api_key.to_owned()));
+        params.push(("sk".to_string(), self.session_key.to_owned()));
+        params.sort();
+        // Requests are signed with the md5 of all sorted parameters followed by the secret
+        let signature = params
+            .iter()
+            .map(|f| format!("{}{}", f.0, f.1))
+            .chain([self.api_secret.to_owned()])
+            .collect::<String>();
+        params.push(("api_sig".to_string(), format!("{:x}", md5::compute(signature))));
+        params.push(("format".to_string(), "json".to_string()));
+        self.agent
+            .post(&self.url)
+            .send_form(
+                &params
+                    .iter()
+                    .map(|f| (f.0.as_str(), f.1.as_str()))
+                    .collect::<Vec<(&str, &str)>>(),
+            )
+            .map_err(Box::new)?;
+        Ok(())
+    }
+}
+
+// The parameters of a track, `suffix` is used to number the tracks of a batch
+fn track_params(listen: &Listen, suffix: &str) -> Vec<(String, String)> {
+    let mut params = vec![
+        (format!("artist{}", suffix), listen.artist.to_owned()),
+        (format!("track{}", suffix), listen.title.to_owned()),
+        (format!("album{}", suffix), listen.album.to_owned()),
+        (format!("duration{}", suffix), listen.length.to_string()),
+    ];
+    if let Some(number) = &listen.number {
+        params.push((format!("trackNumber{}", suffix), number.to_owned()));
+    }
+    params
+}
+
+impl ScrobbleService for LastFm {
+    fn now_playing(&self, listen: &Listen) -> Result<(), Box<ureq::Error>> {
+        self.send("track.updateNowPlaying", track_params(listen, ""))
+    }
+
+    fn submit(&self, listens: &[Listen]) -> Result<(), Box<ureq::Error>> {
+        let params = listens
+            .iter()
+            .enumerate()
+            .flat_map(|(i, f)| {
+                let suffix = format!("[{}]", i);
+                let mut params = track_params(f, &suffix);
+                params.push((format!("timestamp{}", suffix), f.timestamp.to_string()));
+                params
+            })
+            .collect();
+        self.send("track.scrobble", params)
+    }
+
+    fn batch_size(&self) -> usize {
+        50
+    }
+}
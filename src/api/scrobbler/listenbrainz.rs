@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use super::ScrobbleService;
+use crate::api::history::Listen;
+
+// Submits listens to the ListenBrainz API or any server implementing it
+pub struct ListenBrainz {
+    url: String,
+    token: String,
+    agent: ureq::Agent,
+}
+
+impl ListenBrainz {
+    pub fn new(url: String, token: String) -> ListenBrainz {
+        ListenBrainz {
+            url: url.trim_end_matches('/').to_string(),
+            token,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(10))
+                .build(),
+        }
+    }
+    fn send(&self, listen_type: &str, payload: Vec<Value>) -> Result<(), Box<ureq::Error>> {
+        let body = json!({
+            "listen_type": listen_type,
+            "payload": payload,
+        });
+        self.agent
+            .post(&format!("{}/1/submit-listens", self.url))
+            .set("Authorization", &format!("Token {}", self.token))
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string())
+            .map_err(Box::new)?;
+        Ok(())
+    }
+}
+
+fn track_metadata(listen: &Listen) -> Value {
+    let mut additional_info = json!({
+        "duration": listen.length,
+        "media_player": "mplayer",
+        "submission_client": "mplayer",
+        "submission_client_version": env!("CARGO_PKG_VERSION"),
+    });
+    if let Some(number) = &listen.number {
+        additional_info["tracknumber"] = json!(number);
+    }
+    json!({
+        "artist_name": listen.artist,
+        "track_name": listen.title,
+        "release_name": listen.album,
+        "additional_info": additional_info,
+    })
+}
+
+impl ScrobbleService for ListenBrainz {
+    fn now_playing(&self, listen: &Listen) -> Result<(), Box<ureq::Error>> {
+        self.send(
+            "playing_now",
+            vec![json!({ "track_metadata": track_metadata(listen) })],
+        )
+    }
+
+    fn submit(&self, listens: &[Listen]) -> Result<(), Box<ureq::Error>> {
+        let listen_type = if listens.len() == 1 { "single" } else { "import" };
+        self.send(
+            listen_type,
+            listens
+                .iter()
+                .map(|f| {
+                    json!({
+                        "listened_at": f.timestamp,
+                        "track_metadata": track_metadata(f),
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    fn batch_size(&self) -> usize {
+        100
+    }
+}
@@ -0,0 +1,139 @@
+mod lastfm;
+mod listenbrainz;
+
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+use super::history::Listen;
+pub use lastfm::LastFm;
+pub use listenbrainz::ListenBrainz;
+
+// How often queued listens are retried while offline
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+// An online service that listens can be submitted to
+pub trait ScrobbleService: Send {
+    fn now_playing(&self, listen: &Listen) -> Result<(), Box<ureq::Error>>;
+    fn submit(&self, listens: &[Listen]) -> Result<(), Box<ureq::Error>>;
+    // Maximum number of listens per submission
+    fn batch_size(&self) -> usize;
+}
+
+enum ScrobbleEvent {
+    NowPlaying(Box<Listen>),
+    Listen(Box<Listen>),
+}
+
+// Submits listens in the background. Listens are written to a queue on disk first, so they are
+// kept when the service can't be reached and submitted once it's back.
+#[derive(Clone)]
+pub struct Scrobbler {
+    sender: Sender<ScrobbleEvent>,
+}
+
+impl Scrobbler {
+    pub fn new(service: Box<dyn ScrobbleService>, queue_path: PathBuf) -> Scrobbler {
+        let (tx, rx) = mpsc::channel::<ScrobbleEvent>();
+        Scrobbler::start(rx, service, queue_path);
+        Scrobbler { sender: tx }
+    }
+    fn start(
+        rx: Receiver<ScrobbleEvent>,
+        service: Box<dyn ScrobbleService>,
+        queue_path: PathBuf,
+    ) {
+        thread::spawn(move || {
+            let queue = ScrobbleQueue { path: queue_path };
+            // Listens queued in an earlier session
+            let _ = queue.flush(service.as_ref());
+            loop {
+                match rx.recv_timeout(RETRY_INTERVAL) {
+                    Ok(ScrobbleEvent::NowPlaying(listen)) => {
+                        let _ = service.now_playing(&listen);
+                    }
+                    Ok(ScrobbleEvent::Listen(listen)) => {
+                        let _ = queue.push(&listen);
+                        let _ = queue.flush(service.as_ref());
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        let _ = queue.flush(service.as_ref());
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+    }
+    pub fn now_playing(&self, listen: Listen) {
+        let _ = self.sender.send(ScrobbleEvent::NowPlaying(Box::new(listen)));
+    }
+    pub fn scrobble(&self, listen: Listen) {
+        let _ = self.sender.send(ScrobbleEvent::Listen(Box::new(listen)));
+    }
+}
+
+// Listens waiting for submission, stored as one JSON object per line
+struct ScrobbleQueue {
+    path: PathBuf,
+}
+
+impl ScrobbleQueue {
+    fn push(&self, listen: &Listen) -> Result<(), io::Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(listen)?)
+    }
+    fn read(&self) -> Result<Vec<Listen>, io::Error> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        Ok(BufReader::new(File::open(&self.path)?)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|f| serde_json::from_str(&f).ok())
+            .collect())
+    }
+    fn write(&self, listens: &[Listen]) -> Result<(), io::Error> {
+        let mut data = String::new();
+        for listen in listens {
+            data.push_str(&serde_json::to_string(listen)?);
+            data.push('\n');
+        }
+        // Replace the queue atomically, so a crash can't lose listens
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, data)?;
+        fs::rename(tmp_path, &self.path)
+    }
+    // Submits all queued listens until the service fails
+    fn flush(&self, service: &dyn ScrobbleService) -> Result<(), io::Error> {
+        let mut listens = self.read()?;
+        if listens.is_empty() {
+            return Ok(());
+        }
+        while !listens.is_empty() {
+            let batch = listens.len().min(service.batch_size());
+            match service.submit(&listens[..batch]) {
+                Ok(()) => {}
+                // Rejected listens would block the queue forever, so they are dropped. Errors
+                // caused by the credentials or rate limiting are retried later.
+                Err(e) => match *e {
+                    ureq::Error::Status(code, _)
+                        if (400..500).contains(&code) && ![401, 403, 429].contains(&code) => {}
+                    _ => break,
+                },
+            }
+            listens.drain(..batch);
+        }
+        self.write(&listens)
+    }
+}
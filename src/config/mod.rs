@@ -0,0 +1,142 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use dirs::{audio_dir, config_dir, home_dir};
+use serde::Deserialize;
+
+// Settings read from the config file, every missing value falls back to its default
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub library: LibraryConfig,
+    pub history: HistoryConfig,
+    pub scrobbler_log: ScrobblerLogConfig,
+    pub scrobbler: ScrobblerConfig,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct LibraryConfig {
+    // Directories that are scanned for music
+    pub roots: Vec<PathBuf>,
+    // Seconds between rescans of the library if it can't be watched for changes, pressing r
+    // rescans immediately
+    pub rescan_interval: u64,
+    // Days for which added albums are listed as recently added
+    pub recently_added_days: u64,
+    // Where organizing moves the files to, relative to their library root. Available are
+    // {albumartist}, {artist}, {album}, {title}, {genre}, {year}, {track}, {disc} and {ext},
+    // numbers can be padded with zeros like {track:02}.
+    pub organize_template: String,
+    // Fingerprints the audio of every file in the background, which finds duplicates with
+    // different tags. Off by default, as it decodes the whole library once.
+    pub fingerprints: bool,
+}
+
+impl Default for LibraryConfig {
+    fn default() -> Self {
+        LibraryConfig {
+            roots: audio_dir().into_iter().collect(),
+            rescan_interval: 300,
+            recently_added_days: 30,
+            organize_template: "{albumartist}/{year} - {album}/{disc}{track:02} {title}.{ext}"
+                .to_string(),
+            fingerprints: false,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    // Percentage of a song that has to be played to count as listened
+    pub threshold_percentage: f64,
+    // Songs played longer than this always count as listened
+    pub threshold_seconds: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            threshold_percentage: 50.0,
+            threshold_seconds: 240,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ScrobblerLogConfig {
+    pub enabled: bool,
+    // Where to write the .scrobbler.log, defaults to the data dir of mplayer
+    pub path: Option<PathBuf>,
+}
+
+impl Default for ScrobblerLogConfig {
+    fn default() -> Self {
+        ScrobblerLogConfig {
+            enabled: true,
+            path: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrobbleServiceType {
+    #[default]
+    ListenBrainz,
+    LastFm,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ScrobblerConfig {
+    pub enabled: bool,
+    pub service: ScrobbleServiceType,
+    // API root of the service, defaults to the official server of the service
+    pub url: Option<String>,
+    // ListenBrainz user token
+    pub token: String,
+    // Last.fm credentials
+    pub api_key: String,
+    pub api_secret: String,
+    pub session_key: String,
+}
+
+impl Config {
+    // Loads the config file, using the defaults if there is none
+    pub fn load() -> Result<Config, io::Error> {
+        let path = Config::config_path()?;
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let mut config: Config = toml::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.library.roots = config.library.roots.iter().map(|f| expand_home(f)).collect();
+        Ok(config)
+    }
+    // Library roots given on the command line replace the ones of the config file
+    pub fn with_library_roots(mut self, roots: Vec<PathBuf>) -> Config {
+        if !roots.is_empty() {
+            self.library.roots = roots;
+        }
+        self
+    }
+    fn config_path() -> Result<PathBuf, io::Error> {
+        let mut path = config_dir().ok_or(io::Error::other("Failed to find config dir"))?;
+        path.push("mplayer");
+        path.push("config.toml");
+        Ok(path)
+    }
+}
+
+// Replaces a leading ~ with the home directory
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
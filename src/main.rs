@@ -1,15 +1,24 @@
 mod api;
+mod config;
 mod ui;
 
+use config::Config;
 use rodio::{OutputStream, Sink};
+use std::{env, path::PathBuf, process};
 use ui::interface;
 use ui::{input::create_input_channel, interface::setup_terminal};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // parse the command line first, so the help works without an audio device
+    let library_roots = parse_args();
+
     // test if the audio sink works
     test_sink()?;
 
+    // load the config before taking over the terminal, so errors are readable
+    let config = Config::load()?.with_library_roots(library_roots);
+
     // setup input handler
     let rx = create_input_channel();
 
@@ -17,7 +26,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut terminal = setup_terminal()?;
 
     // render the main interface
-    interface::render_interface(&mut terminal, rx).await;
+    interface::render_interface(&mut terminal, rx, config).await;
 
     // restore terminal
     interface::restore_terminal(&mut terminal)?;
@@ -25,6 +34,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/**
+ * Parses the command line, which currently only consists of library roots.
+ */
+fn parse_args() -> Vec<PathBuf> {
+    let mut roots = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-l" | "--library" => match args.next() {
+                Some(root) => roots.push(PathBuf::from(root)),
+                None => exit_with_usage(1),
+            },
+            "-h" | "--help" => exit_with_usage(0),
+            _ => exit_with_usage(1),
+        }
+    }
+    roots
+}
+
+fn exit_with_usage(code: i32) -> ! {
+    println!("Usage: mplayer [-l|--library <dir>]...");
+    println!();
+    println!("  -l, --library <dir>  Scan <dir> for music, can be given multiple times.");
+    println!("                       Replaces the library roots of the config file.");
+    println!("  -h, --help           Print this help");
+    process::exit(code)
+}
+
 /**
  * Code for testing if the audio sink can be owned by the program, because checking in the thread
  * owning the sink is pain.
@@ -1,25 +1,65 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, path::PathBuf};
 
+use super::interface::TagEditor;
 use super::scroll_components::ScrollTable;
-use crate::api::{player::SongInfo, Album, Artist, Song};
+use itertools::Itertools;
+use crate::api::{
+    fs::{
+        album_added, is_multi_disc, Duplicate, DuplicateProgress, FingerprintProgress, PlannedMove,
+        ScanProgress,
+    },
+    history::{ListenHistory, ListenStats},
+    player::SongInfo,
+    Album, Artist, Song,
+};
 
 use ratatui::{
-    layout::Constraint,
+    layout::{Alignment, Constraint},
     style::{Color, Modifier, Style},
     text::Span,
     text::Line,
-    widgets::{Block, Borders, Cell, Gauge, Paragraph, Row, Table},
+    widgets::{block::Title, Block, Borders, Cell, Gauge, Paragraph, Row, Table},
 };
 
 pub enum ToolbarType {
     Default,
     Download,
     Play,
+    // Views without actions on their content
+    Browse,
+    // Views of several albums, which are played together
+    PlayAll,
+    Organize,
+    Duplicates,
 }
 
-// The main window
-pub fn build_window_border() -> Block<'static> {
-    Block::default().title("mplayer").borders(Borders::ALL)
+// The main window, showing the progress of a running library scan or of fingerprinting
+pub fn build_window_border(
+    scan_progress: ScanProgress,
+    fingerprint_progress: Option<FingerprintProgress>,
+) -> Block<'static> {
+    let block = Block::default().title("mplayer").borders(Borders::ALL);
+    if !scan_progress.running {
+        return match fingerprint_progress.filter(|f| f.processed < f.total) {
+            Some(f) => block.title(
+                Title::from(format!(" Fingerprinting: {}/{} ", f.processed, f.total))
+                    .alignment(Alignment::Right),
+            ),
+            None => block,
+        };
+    }
+    let mut status = if scan_progress.changed == 0 {
+        format!(" Scanning library: {} files ", scan_progress.seen)
+    } else {
+        format!(
+            " Reading files: {}/{} ",
+            scan_progress.processed, scan_progress.changed
+        )
+    };
+    if scan_progress.failed > 0 {
+        status.push_str(&format!("({} failed) ", scan_progress.failed));
+    }
+    block.title(Title::from(status).alignment(Alignment::Right))
 }
 
 // The menu on the left side
@@ -36,6 +76,37 @@ pub fn build_libary(
         .block(Block::default().borders(Borders::all()).title("[L]ibary"))
 }
 
+// The genres of the library, shown instead of the libary
+pub fn build_genre_list(
+    content: Vec<String>,
+    index: Option<usize>,
+    displayable_results: usize,
+) -> Table<'static> {
+    let rows = content.into_iter().map(|f| vec![f]).collect();
+    ScrollTable::new(rows, vec![Constraint::Percentage(100)])
+        .focus(index)
+        .displayable_results(displayable_results)
+        .render()
+        .block(Block::default().borders(Borders::all()).title("[G]enres"))
+}
+
+// The decades albums were released in
+pub fn build_decade_list(
+    content: Vec<Option<i32>>,
+    index: Option<usize>,
+    displayable_results: usize,
+) -> Table<'static> {
+    let rows = content
+        .into_iter()
+        .map(|f| vec![f.map_or("Unknown year".to_string(), |d| format!("{}s", d))])
+        .collect();
+    ScrollTable::new(rows, vec![Constraint::Percentage(100)])
+        .focus(index)
+        .displayable_results(displayable_results)
+        .render()
+        .block(Block::default().borders(Borders::all()).title("[Y]ears"))
+}
+
 // The welcome window
 pub fn build_help_window() -> Table<'static> {
     Table::new(vec![
@@ -43,6 +114,11 @@ pub fn build_help_window() -> Table<'static> {
             Cell::from("Global").style(Style::default().add_modifier(Modifier::BOLD))
         ]),
         Row::new(vec!["L", "Libary"]),
+        Row::new(vec!["G", "Genres"]),
+        Row::new(vec!["Y", "Years"]),
+        Row::new(vec!["a", "Recently added"]),
+        Row::new(vec!["O", "Organize files"]),
+        Row::new(vec!["F", "Find duplicates"]),
         Row::new(vec!["Q", "Queue"]),
         Row::new(vec!["s", "Search"]),
         Row::new(vec!["h", "Help"]),
@@ -53,6 +129,11 @@ pub fn build_help_window() -> Table<'static> {
         Row::new(vec!["v", "Stop"]),
         Row::new(vec!["n", "Skip"]),
         Row::new(vec!["c", "Current album"]),
+        Row::new(vec!["r", "Rescan library"]),
+        Row::new(vec!["u", "Undo delete"]),
+        Row::new(vec!["E", "Empty trash"]),
+        Row::new(vec!["[", "Previous chapter"]),
+        Row::new(vec!["]", "Next chapter"]),
         Row::new(vec!["b", "Back"]),
         Row::new(vec![
             Cell::from("Search").style(Style::default().add_modifier(Modifier::BOLD))
@@ -67,6 +148,9 @@ pub fn build_help_window() -> Table<'static> {
         Row::new(vec!["d", "Download media"]),
         Row::new(vec!["p", "Play media"]),
         Row::new(vec!["e", "Enqueue media"]),
+        Row::new(vec!["t", "Edit tags"]),
+        Row::new(vec!["o", "Order by play count"]),
+        Row::new(vec!["D", "Delete media"]),
     ], &[Constraint::Percentage(20), Constraint::Percentage(80)])
     .block(Block::default().borders(Borders::ALL).title("Help"))
 }
@@ -85,9 +169,13 @@ pub fn build_focus_toolbox(toolbar_type: ToolbarType) -> Paragraph<'static> {
     Paragraph::new(match toolbar_type {
         ToolbarType::Download => "[b]ack [d]ownload [↑]up [↓]down [enter]select".to_string(),
         ToolbarType::Play => {
-            "[b]ack [p]lay [e]nqueue [↑]up [↓]down [enter]select [D]elete".to_string()
+            "[b]ack [p]lay [e]nqueue [↑]up [↓]down [enter]select [t]ags [D]elete".to_string()
         }
         ToolbarType::Default => "[b]ack [↑]up [↓]down [enter]select [D]elete".to_string(),
+        ToolbarType::Browse => "[b]ack [↑]up [↓]down [enter]select".to_string(),
+        ToolbarType::Organize => "[b]ack [↑]up [↓]down [M]ove files".to_string(),
+        ToolbarType::Duplicates => "[b]ack [↑]up [↓]down [enter]select [k]eep and trash the others".to_string(),
+        ToolbarType::PlayAll => "[b]ack [p]lay all [e]nqueue all [↑]up [↓]down [enter]select".to_string(),
     })
 }
 
@@ -118,12 +206,13 @@ pub fn build_result_box<T: SearchEntity>(
 }
 
 pub fn build_artist_focus(
-    artist: Box<dyn Artist>,
+    name: String,
+    albums: Vec<Box<dyn Album + Send + Sync>>,
     index: Option<usize>,
     displayable_results: usize,
 ) -> Table<'static> {
     let mut rows = vec![];
-    for r in artist.get_albums() {
+    for r in albums {
         rows.push(vec![r.get_name(), r.get_release_date()]);
     }
 
@@ -134,12 +223,191 @@ pub fn build_artist_focus(
         .block(
             Block::default()
                 .borders(Borders::all())
-                .title(artist.get_name()),
+                .title(name),
         )
         .header(Row::new(vec!["Title", "Release Date"]))
 }
 
-pub fn build_song_focus(song: Box<dyn Song>) -> Table<'static> {
+pub fn build_genre_focus(
+    genre: String,
+    artists: Vec<Box<dyn Artist + Send + Sync>>,
+    index: Option<usize>,
+    displayable_results: usize,
+) -> Table<'static> {
+    let rows = artists
+        .iter()
+        .map(|f| vec![f.get_name(), f.get_albums().len().to_string()])
+        .collect();
+
+    ScrollTable::new(rows, vec![Constraint::Max(u16::MAX), Constraint::Length(8)])
+        .focus(index)
+        .displayable_results(displayable_results)
+        .render()
+        .block(Block::default().borders(Borders::all()).title(genre))
+        .header(Row::new(vec!["Artist", "Albums"]))
+}
+
+pub fn build_decade_focus(
+    decade: i32,
+    years: Vec<i32>,
+    index: Option<usize>,
+    displayable_results: usize,
+) -> Table<'static> {
+    let rows = years.into_iter().map(|f| vec![f.to_string()]).collect();
+
+    ScrollTable::new(rows, vec![Constraint::Percentage(100)])
+        .focus(index)
+        .displayable_results(displayable_results)
+        .render()
+        .block(
+            Block::default()
+                .borders(Borders::all())
+                .title(format!("{}s", decade)),
+        )
+}
+
+pub fn build_year_focus(
+    year: Option<i32>,
+    albums: Vec<Box<dyn Album + Send + Sync>>,
+    index: Option<usize>,
+    displayable_results: usize,
+) -> Table<'static> {
+    let rows = albums
+        .iter()
+        .map(|f| vec![f.get_name(), f.get_artist_name()])
+        .collect();
+
+    ScrollTable::new(rows, vec![Constraint::Max(u16::MAX), Constraint::Percentage(40)])
+        .focus(index)
+        .displayable_results(displayable_results)
+        .render()
+        .block(
+            Block::default()
+                .borders(Borders::all())
+                .title(year.map_or("Unknown year".to_string(), |f| f.to_string())),
+        )
+        .header(Row::new(vec!["Title", "Artist"]))
+}
+
+pub fn build_recently_added(
+    albums: Vec<Box<dyn Album + Send + Sync>>,
+    index: Option<usize>,
+    displayable_results: usize,
+) -> Table<'static> {
+    let rows = albums
+        .iter()
+        .map(|f| {
+            vec![
+                f.get_name(),
+                f.get_artist_name(),
+                album_added(f.as_ref())
+                    .map(|f| f.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    ScrollTable::new(rows, vec![Constraint::Max(u16::MAX), Constraint::Percentage(40), Constraint::Length(10)])
+        .focus(index)
+        .displayable_results(displayable_results)
+        .render()
+        .block(Block::default().borders(Borders::all()).title("Recently added"))
+        .header(Row::new(vec!["Title", "Artist", "Added"]))
+}
+
+// The moves planned for organizing the library, nothing is moved before confirming
+pub fn build_organize_preview(
+    moves: Vec<PlannedMove>,
+    index: Option<usize>,
+    displayable_results: usize,
+) -> Table<'static> {
+    let conflicts = moves.iter().filter(|f| f.conflict.is_some()).count();
+    let relative = |path: &PathBuf, root: &PathBuf| {
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    };
+    let rows = moves
+        .iter()
+        .map(|f| {
+            vec![
+                relative(&f.from, &f.root),
+                relative(&f.to, &f.root),
+                f.conflict.to_owned().unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    ScrollTable::new(rows, vec![Constraint::Percentage(40), Constraint::Percentage(40), Constraint::Percentage(20)])
+        .focus(index)
+        .displayable_results(displayable_results)
+        .render()
+        .block(Block::default().borders(Borders::all()).title(format!(
+            "Organize: {} files to move, {} conflicts",
+            moves.len() - conflicts,
+            conflicts
+        )))
+        .header(Row::new(vec!["From", "To", "Conflict"]))
+}
+
+// Shown while the songs of the library are compared to find duplicates
+pub fn build_duplicate_search(progress: DuplicateProgress) -> Paragraph<'static> {
+    Paragraph::new(format!(
+        "Comparing songs: {}/{}",
+        progress.processed, progress.total
+    ))
+    .block(Block::default().borders(Borders::all()).title("Duplicates"))
+}
+
+// Likely duplicates, the songs of a group are next to each other with the highest bitrate first
+pub fn build_duplicates(
+    duplicates: Vec<Duplicate>,
+    index: Option<usize>,
+    displayable_results: usize,
+) -> Table<'static> {
+    let groups = duplicates.iter().map(|f| f.group).unique().count();
+    let rows = duplicates
+        .iter()
+        .map(|f| {
+            vec![
+                (f.group + 1).to_string(),
+                f.song.get_artist_name(),
+                f.song.get_title(),
+                f.song.get_length().unwrap_or_default(),
+                f.format.to_owned(),
+                f.bitrate.map(|f| format!("{} kbit/s", f)).unwrap_or_default(),
+                if f.identical { "identical" } else { "" }.to_string(),
+                f.song
+                    .get_filepath()
+                    .map(|f| f.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    ScrollTable::new(rows, vec![
+        Constraint::Length(4),
+        Constraint::Percentage(15),
+        Constraint::Percentage(20),
+        Constraint::Length(6),
+        Constraint::Length(6),
+        Constraint::Length(12),
+        Constraint::Length(9),
+        Constraint::Percentage(40),
+    ])
+        .focus(index)
+        .displayable_results(displayable_results)
+        .render()
+        .block(Block::default().borders(Borders::all()).title(format!(
+            "Duplicates: {} songs in {} groups",
+            duplicates.len(),
+            groups
+        )))
+        .header(Row::new(vec!["#", "Artist", "Title", "Length", "Format", "Bitrate", "Audio", "Path"]))
+}
+
+pub fn build_song_focus(song: Box<dyn Song>, stats: ListenStats) -> Table<'static> {
     let title = format!(
         "{}{}",
         song.get_title(),
@@ -157,42 +425,51 @@ pub fn build_song_focus(song: Box<dyn Song>) -> Table<'static> {
         String::from("1"),
         title,
         song.get_length().unwrap_or("00:00".to_string()),
+        stats.play_count.to_string(),
+        stats
+            .last_played
+            .map(|f| f.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or("never".to_string()),
     ]);
     return Table::new(vec![content], &[
             Constraint::Length(3),
             Constraint::Max(u16::MAX),
             Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Length(16),
         ])
         .block(
             Block::default()
                 .borders(Borders::all())
                 .title(song.get_title()),
         )
-        .header(Row::new(vec!["#", "Title", "Length"]))
+        .header(Row::new(vec!["#", "Title", "Length", "Plays", "Last played"]))
 }
 
-pub fn build_record_focus(
-    record: Box<dyn Album>,
+pub fn build_chapter_list(
+    song: Box<dyn Song>,
     index: Option<usize>,
     displayable_results: usize,
     current_song: &Option<SongInfo>,
 ) -> Table<'static> {
-    let playing = if current_song.is_some() {
-        record
-            .get_songs()
-            .iter()
-            .position(|x| x.get_title() == current_song.to_owned().unwrap().name)
-    } else {
-        None
-    };
-    let rows: Vec<Vec<String>> = record
-        .get_songs()
+    let playing = current_song
+        .as_ref()
+        .filter(|f| {
+            f.name == song.get_title()
+                && f.artist == song.get_artist_name()
+                && f.album == song.get_album_name()
+        })
+        .and_then(|f| f.current_chapter());
+    let rows: Vec<Vec<String>> = song
+        .get_chapters()
         .into_iter()
-        .map(|f| {
+        .enumerate()
+        .map(|(i, f)| {
+            let start = f.start.as_secs();
             vec![
-                f.get_number().unwrap_or("".to_string()),
-                f.get_title(),
-                f.get_length().unwrap_or("00:00".to_string()),
+                (i + 1).to_string(),
+                f.title,
+                format!("{:0>2}:{:0>2}:{:0>2}", start / 3600, (start / 60) % 60, start % 60),
             ]
         })
         .collect();
@@ -202,7 +479,70 @@ pub fn build_record_focus(
         .selected(playing)
         .displayable_results(displayable_results)
         .render()
-        .header(Row::new(vec!["#", "Title", "Length"]))
+        .header(Row::new(vec!["#", "Chapter", "Start"]))
+        .block(Block::default().borders(Borders::all()).title("Chapters"))
+}
+
+// The songs are passed in the order they are listed, see UiState::album_songs
+pub fn build_record_focus(
+    record: Box<dyn Album>,
+    songs: Vec<Box<dyn Song + Send + Sync>>,
+    index: Option<usize>,
+    displayable_results: usize,
+    current_song: &Option<SongInfo>,
+    history: &ListenHistory,
+) -> Table<'static> {
+    let playing = if current_song.is_some() {
+        songs
+            .iter()
+            .position(|x| x.get_title() == current_song.to_owned().unwrap().name)
+    } else {
+        None
+    };
+    // Albums spread over several discs get a row in front of every disc, unless the songs are
+    // sorted by plays and the discs are mixed
+    let multi_disc = is_multi_disc(&songs)
+        && songs.windows(2).all(|f| f[0].get_disc_number() <= f[1].get_disc_number());
+    // Compilations show the performer of every song
+    let show_artists = songs.iter().map(|f| f.get_artist_name()).unique().count() > 1;
+    let mut header = vec!["#", "Title"];
+    let mut constraints = vec![Constraint::Length(3), Constraint::Max(u16::MAX)];
+    if show_artists {
+        header.push("Artist");
+        constraints.push(Constraint::Percentage(35));
+    }
+    header.extend(["Length", "Plays"]);
+    constraints.extend([Constraint::Length(8), Constraint::Length(6)]);
+    let mut rows: Vec<Vec<String>> = vec![];
+    // Row of each song, which is moved down by the disc rows
+    let mut song_rows = vec![];
+    let mut last_disc = None;
+    for song in &songs {
+        let disc = song.get_disc_number().unwrap_or(1);
+        if multi_disc && last_disc != Some(disc) {
+            let mut separator = vec!["".to_string(); header.len()];
+            separator[1] = format!("Disc {}", disc);
+            rows.push(separator);
+            last_disc = Some(disc);
+        }
+        song_rows.push(rows.len());
+        let mut row = vec![song.get_number().unwrap_or("".to_string()), song.get_title()];
+        if show_artists {
+            row.push(song.get_artist_name());
+        }
+        row.extend([
+            song.get_length().unwrap_or("00:00".to_string()),
+            history.get_stats(song.as_ref()).play_count.to_string(),
+        ]);
+        rows.push(row);
+    }
+
+    ScrollTable::new(rows, constraints)
+        .focus(index.and_then(|f| song_rows.get(f).copied()))
+        .selected(playing.and_then(|f| song_rows.get(f).copied()))
+        .displayable_results(displayable_results)
+        .render()
+        .header(Row::new(header))
         .block(
             Block::default()
                 .borders(Borders::all())
@@ -210,6 +550,42 @@ pub fn build_record_focus(
         )
 }
 
+// The popup for editing tags, saved with enter and closed with esc
+pub fn build_tag_editor(editor: &TagEditor) -> Table<'static> {
+    let mut rows = editor
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let row = Row::new(vec![f.name.to_string(), f.value.to_owned()]);
+            if i == editor.focused {
+                row.style(Style::default().add_modifier(Modifier::BOLD))
+            } else {
+                row
+            }
+        })
+        .collect_vec();
+    if let Some(error) = &editor.error {
+        rows.push(Row::new(vec![""]));
+        rows.push(Row::new(vec![error.to_owned()]).style(Style::default().fg(Color::Red)));
+    }
+    let title = if editor.songs.len() > 1 {
+        format!("Edit tags of {} songs", editor.songs.len())
+    } else {
+        "Edit tags".to_string()
+    };
+    Table::new(rows, &[Constraint::Length(14), Constraint::Max(u16::MAX)])
+        .block(
+            Block::default()
+                .borders(Borders::all())
+                .title(title)
+                .title(
+                    Title::from(" [enter]save [esc]cancel ")
+                        .position(ratatui::widgets::block::Position::Bottom),
+                ),
+        )
+}
+
 pub fn build_song_info(song_info: &SongInfo) -> Paragraph<'static> {
     Paragraph::new(vec![
         Line::from(format!("{} - {}", song_info.name, song_info.artist)),
@@ -1,8 +1,10 @@
 use crossterm::event::{self, KeyCode, KeyEvent};
 
-use super::interface::{Focus, FocusedResult, MainWindowState, SideMenu, UiState};
+use super::interface::{Deletion, DeletionTarget, Focus, FocusedResult, MainWindowState, SideMenu, TagEditor, UiState};
+use crate::api::fs::disk_usage;
 use std::collections::HashMap;
-use std::sync::mpsc;
+use std::io;
+use std::sync::{mpsc, Arc, Mutex};
 use std::sync::mpsc::Receiver;
 use std::time::{Duration, Instant};
 use std::thread;
@@ -25,30 +27,93 @@ impl InputHandler {
         self
     }
     pub fn handle(&self, input: KeyEvent, ui_state: &mut UiState) {
-        self.handlers.iter().for_each(|handler| handler.handle(input, ui_state))
+        // The handlers are chosen before handling the key, so a key switching to another mode
+        // isn't handled again by the handler of that mode
+        let active = self
+            .handlers
+            .iter()
+            .filter(|handler| handler.is_active(ui_state))
+            .collect::<Vec<_>>();
+        active.into_iter().for_each(|handler| handler.handle(input, ui_state))
     }
     pub fn load_input_handlers(self) -> InputHandler {
-        let main_input_handler = ConditionalHandler::new(|ui_state| !ui_state.searching && !ui_state.delete)
-            .register_handler(KeyCode::Char('p'), |ui_state| match ui_state.main_window_state.to_owned() {
-                MainWindowState::SongFocus(song) => ui_state.music_player.play_song(song, true),
-                MainWindowState::RecordFocus(record, _) => ui_state.music_player.play_album(record, true),
-                _ => {}
+        let main_input_handler = ConditionalHandler::new(|ui_state| !ui_state.searching && ui_state.delete.is_none() && ui_state.tag_editor.is_none() && ui_state.notice.is_none())
+            .register_handler(KeyCode::Char('p'), |ui_state| {
+                let result = match ui_state.main_window_state.to_owned() {
+                    MainWindowState::SongFocus(song, _) => ui_state.music_player.play_song(song, true),
+                    MainWindowState::RecordFocus(record, _) => ui_state.music_player.play_album(record, true),
+                    MainWindowState::YearFocus(_, albums, _) => albums.into_iter().enumerate().try_for_each(|(i, album)| {
+                        ui_state.music_player.play_album(album, i == 0)
+                    }),
+                    _ => Ok(()),
+                };
+                show_play_error(ui_state, result);
             }
         ).unwrap()
-        .register_handler(KeyCode::Char('e'), |ui_state| match ui_state.main_window_state.to_owned() {
-            MainWindowState::SongFocus(song) => ui_state.music_player.play_song(song, false),
-            MainWindowState::RecordFocus(record, _) => ui_state.music_player.play_album(record, false),
-            _ => {}
+        .register_handler(KeyCode::Char('e'), |ui_state| {
+            let result = match ui_state.main_window_state.to_owned() {
+                MainWindowState::SongFocus(song, _) => ui_state.music_player.play_song(song, false),
+                MainWindowState::RecordFocus(record, _) => ui_state.music_player.play_album(record, false),
+                MainWindowState::YearFocus(_, albums, _) => albums.into_iter().try_for_each(|album| {
+                    ui_state.music_player.play_album(album, false)
+                }),
+                _ => Ok(()),
+            };
+            show_play_error(ui_state, result);
         }
     ).unwrap()
-        .register_handler(KeyCode::Char('D'), |ui_state| match ui_state.main_window_state.to_owned() {
-           MainWindowState::ArtistFocus(_, _) | MainWindowState::RecordFocus(_, _) | MainWindowState::SongFocus(_) => ui_state.delete = true,
-           _ => {}
+        .register_handler(KeyCode::Char('D'), |ui_state| {
+            let files = match ui_state.main_window_state.to_owned() {
+                MainWindowState::ArtistFocus(a, _) => a.get_files(),
+                MainWindowState::RecordFocus(r, _) => r.get_files(),
+                // Tracks of cue sheets share their file with the other tracks of the album
+                MainWindowState::SongFocus(s, _) if s.get_start().is_some() => {
+                    ui_state.notice = Some("The song is part of a disc image, delete the album instead".to_string());
+                    return;
+                },
+                MainWindowState::SongFocus(s, _) => s.get_files(),
+                _ => return,
+            };
+            ui_state.delete = Some(Deletion::new(DeletionTarget::Media, disk_usage(&files)));
+        }).unwrap()
+        .register_handler(KeyCode::Char('u'), |ui_state| {
+            ui_state.notice = Some(match ui_state.music_repository.undo_delete() {
+                Ok(0) => "Nothing to restore".to_string(),
+                Ok(1) => "Restored 1 file".to_string(),
+                Ok(n) => format!("Restored {} files", n),
+                Err(error) => format!("Restoring failed: {}", error),
+            });
+        }).unwrap()
+        .register_handler(KeyCode::Char('E'), |ui_state| {
+            ui_state.delete = Some(Deletion::new(DeletionTarget::Trash, ui_state.music_repository.trash_usage()));
+        }).unwrap()
+        .register_handler(KeyCode::Char('t'), |ui_state| match ui_state.main_window_state.to_owned() {
+            MainWindowState::SongFocus(song, _) if song.is_local() => ui_state.tag_editor = Some(TagEditor::for_song(song)),
+            MainWindowState::RecordFocus(record, _) if record.is_local() && !record.get_songs().is_empty() => {
+                ui_state.tag_editor = Some(TagEditor::for_album(record))
+            },
+            _ => {}
+        }).unwrap()
+        .register_handler(KeyCode::Char('o'), |ui_state| match ui_state.main_window_state.to_owned() {
+            // The focus is cleared, as the focused row moves to another song or album
+            MainWindowState::ArtistFocus(a, _) => {
+                ui_state.sort_by_plays = !ui_state.sort_by_plays;
+                ui_state.main_window_state = MainWindowState::ArtistFocus(a, None);
+            },
+            MainWindowState::RecordFocus(r, _) => {
+                ui_state.sort_by_plays = !ui_state.sort_by_plays;
+                ui_state.main_window_state = MainWindowState::RecordFocus(r, None);
+            },
+            _ => {}
         }).unwrap()
         .register_handler(KeyCode::Char(' '), |ui_state| ui_state.music_player.pause())
         .unwrap()
         .register_handler(KeyCode::Char('n'), |ui_state| ui_state.music_player.skip())
         .unwrap()
+        .register_handler(KeyCode::Char(']'), |ui_state| ui_state.music_player.next_chapter())
+        .unwrap()
+        .register_handler(KeyCode::Char('['), |ui_state| ui_state.music_player.previous_chapter())
+        .unwrap()
         .register_handler(KeyCode::Char('v'), |ui_state| ui_state.music_player.stop())
         .unwrap()
         .register_handler(KeyCode::Char('q'), |ui_state| ui_state.quit = true)
@@ -57,6 +122,8 @@ impl InputHandler {
         .unwrap()
         .register_handler(KeyCode::Char('-'), |ui_state| ui_state.music_player.change_volume(-0.1))
         .unwrap()
+        .register_handler(KeyCode::Char('r'), |ui_state| ui_state.music_repository.rescan())
+        .unwrap()
         .register_handler(KeyCode::Char('h'), |ui_state| {
             ui_state.main_window_state = MainWindowState::Help;
             ui_state.focus = Focus::None;
@@ -65,6 +132,37 @@ impl InputHandler {
            let current_album = ui_state.music_repository.find_current_album(&ui_state.music_player.get_song_info().unwrap()); 
            if current_album.is_some() { ui_state.main_window_state = MainWindowState::RecordFocus(current_album.unwrap(), None) }
         }).unwrap()
+        .register_handler(KeyCode::Char('a'), |ui_state| {
+            let albums = ui_state.music_repository.get_recently_added(ui_state.recently_added_window);
+            ui_state.history.push_front(ui_state.main_window_state.to_owned());
+            ui_state.main_window_state = MainWindowState::RecentlyAdded(albums, None);
+            ui_state.focus = Focus::MainWindow;
+        }).unwrap()
+        .register_handler(KeyCode::Char('O'), |ui_state| {
+            let moves = ui_state.music_repository.plan_organize(&ui_state.organize_template);
+            ui_state.history.push_front(ui_state.main_window_state.to_owned());
+            ui_state.main_window_state = MainWindowState::Organize(moves, None);
+            ui_state.focus = Focus::MainWindow;
+        }).unwrap()
+        .register_handler(KeyCode::Char('F'), |ui_state| {
+            let search = ui_state.music_repository.find_duplicates();
+            ui_state.history.push_front(ui_state.main_window_state.to_owned());
+            ui_state.main_window_state = MainWindowState::FindingDuplicates(search);
+            ui_state.focus = Focus::MainWindow;
+        }).unwrap()
+        .register_handler(KeyCode::Char('k'), |ui_state| if let MainWindowState::Duplicates(d, Some(i)) = ui_state.main_window_state.to_owned() {
+            let files = ui_state.music_repository.get_redundant(&d, i).iter().flat_map(|f| f.get_files()).collect::<Vec<_>>();
+            ui_state.delete = Some(Deletion::new(DeletionTarget::Duplicates, disk_usage(&files)));
+        }).unwrap()
+        .register_handler(KeyCode::Char('M'), |ui_state| if let MainWindowState::Organize(moves, _) = ui_state.main_window_state.to_owned() {
+            // Only the moves that failed or have conflicts are left in the preview
+            let (moved, remaining) = ui_state.music_repository.organize(moves);
+            let moved = moved.into_iter().map(|f| (f.from, f.to)).collect::<Vec<_>>();
+            // Play counts are stored by path, so they move along with the files
+            let _ = ui_state.listen_history.rename(&moved);
+            ui_state.refresh_history(&moved.into_iter().collect());
+            ui_state.main_window_state = MainWindowState::Organize(remaining, None);
+        }).unwrap()
         .register_handler(KeyCode::Char('s'), |ui_state| {
             ui_state.searching = true;
             ui_state.focused_result = FocusedResult::None;
@@ -87,6 +185,46 @@ impl InputHandler {
             match ui_state.main_window_state.to_owned() {
                 MainWindowState::RecordFocus(r, _) => ui_state.main_window_state = MainWindowState::RecordFocus(r, None),
                 MainWindowState::ArtistFocus(a, _) => ui_state.main_window_state = MainWindowState::ArtistFocus(a, None),
+                MainWindowState::GenreFocus(g, a, _) => ui_state.main_window_state = MainWindowState::GenreFocus(g, a, None),
+                MainWindowState::DecadeFocus(d, y, _) => ui_state.main_window_state = MainWindowState::DecadeFocus(d, y, None),
+                MainWindowState::YearFocus(y, a, _) => ui_state.main_window_state = MainWindowState::YearFocus(y, a, None),
+                MainWindowState::RecentlyAdded(a, _) => ui_state.main_window_state = MainWindowState::RecentlyAdded(a, None),
+                MainWindowState::Organize(m, _) => ui_state.main_window_state = MainWindowState::Organize(m, None),
+                MainWindowState::Duplicates(d, _) => ui_state.main_window_state = MainWindowState::Duplicates(d, None),
+                _ => {}
+            }
+        } else {
+            ui_state.focus = Focus::SideWindow;
+        }).unwrap()
+        .register_handler(KeyCode::Char('G'), |ui_state| if !matches!(ui_state.side_menu, SideMenu::Genres(_)) {
+            ui_state.side_menu = SideMenu::Genres(None);
+            ui_state.focus = Focus::SideWindow;
+            match ui_state.main_window_state.to_owned() {
+                MainWindowState::RecordFocus(r, _) => ui_state.main_window_state = MainWindowState::RecordFocus(r, None),
+                MainWindowState::ArtistFocus(a, _) => ui_state.main_window_state = MainWindowState::ArtistFocus(a, None),
+                MainWindowState::GenreFocus(g, a, _) => ui_state.main_window_state = MainWindowState::GenreFocus(g, a, None),
+                MainWindowState::DecadeFocus(d, y, _) => ui_state.main_window_state = MainWindowState::DecadeFocus(d, y, None),
+                MainWindowState::YearFocus(y, a, _) => ui_state.main_window_state = MainWindowState::YearFocus(y, a, None),
+                MainWindowState::RecentlyAdded(a, _) => ui_state.main_window_state = MainWindowState::RecentlyAdded(a, None),
+                MainWindowState::Organize(m, _) => ui_state.main_window_state = MainWindowState::Organize(m, None),
+                MainWindowState::Duplicates(d, _) => ui_state.main_window_state = MainWindowState::Duplicates(d, None),
+                _ => {}
+            }
+        } else {
+            ui_state.focus = Focus::SideWindow;
+        }).unwrap()
+        .register_handler(KeyCode::Char('Y'), |ui_state| if !matches!(ui_state.side_menu, SideMenu::Decades(_)) {
+            ui_state.side_menu = SideMenu::Decades(None);
+            ui_state.focus = Focus::SideWindow;
+            match ui_state.main_window_state.to_owned() {
+                MainWindowState::RecordFocus(r, _) => ui_state.main_window_state = MainWindowState::RecordFocus(r, None),
+                MainWindowState::ArtistFocus(a, _) => ui_state.main_window_state = MainWindowState::ArtistFocus(a, None),
+                MainWindowState::GenreFocus(g, a, _) => ui_state.main_window_state = MainWindowState::GenreFocus(g, a, None),
+                MainWindowState::DecadeFocus(d, y, _) => ui_state.main_window_state = MainWindowState::DecadeFocus(d, y, None),
+                MainWindowState::YearFocus(y, a, _) => ui_state.main_window_state = MainWindowState::YearFocus(y, a, None),
+                MainWindowState::RecentlyAdded(a, _) => ui_state.main_window_state = MainWindowState::RecentlyAdded(a, None),
+                MainWindowState::Organize(m, _) => ui_state.main_window_state = MainWindowState::Organize(m, None),
+                MainWindowState::Duplicates(d, _) => ui_state.main_window_state = MainWindowState::Duplicates(d, None),
                 _ => {}
             }
         } else {
@@ -98,6 +236,12 @@ impl InputHandler {
             match ui_state.main_window_state.to_owned() {
                 MainWindowState::RecordFocus(r, _) => ui_state.main_window_state = MainWindowState::RecordFocus(r, None),
                 MainWindowState::ArtistFocus(a, _) => ui_state.main_window_state = MainWindowState::ArtistFocus(a, None),
+                MainWindowState::GenreFocus(g, a, _) => ui_state.main_window_state = MainWindowState::GenreFocus(g, a, None),
+                MainWindowState::DecadeFocus(d, y, _) => ui_state.main_window_state = MainWindowState::DecadeFocus(d, y, None),
+                MainWindowState::YearFocus(y, a, _) => ui_state.main_window_state = MainWindowState::YearFocus(y, a, None),
+                MainWindowState::RecentlyAdded(a, _) => ui_state.main_window_state = MainWindowState::RecentlyAdded(a, None),
+                MainWindowState::Organize(m, _) => ui_state.main_window_state = MainWindowState::Organize(m, None),
+                MainWindowState::Duplicates(d, _) => ui_state.main_window_state = MainWindowState::Duplicates(d, None),
                 _ => {}
             }
         } else {
@@ -120,23 +264,140 @@ impl InputHandler {
             }).unwrap()
             .register_handler(KeyCode::Backspace, |ui_state| {ui_state.searchbar_content.pop();}).unwrap()
             .global_handler(|ui_state, c| ui_state.searchbar_content.push(c));
-        let delete_handler = ConditionalHandler::new(|ui_state| ui_state.delete)
-            .register_handler(KeyCode::Char('y'), |ui_state| {
-                match ui_state.main_window_state.to_owned() {
-                    MainWindowState::SongFocus(s) => ui_state.music_repository.remove_song(s),
-                    MainWindowState::RecordFocus(r, _) => ui_state.music_repository.remove_album(r),
-                    MainWindowState::ArtistFocus(a, _) => ui_state.music_repository.remove_artist(a),
-                    _ => {},
-                }
-                ui_state.delete = false;
+        // The dialog ignores keys while the deletion is running
+        let delete_handler = ConditionalHandler::new(|ui_state| ui_state.delete.as_ref().is_some_and(|f| f.running.is_none()))
+            .register_handler(KeyCode::Char('y'), delete).unwrap()
+        .register_handler(KeyCode::Char('n'), |ui_state| ui_state.delete = None).unwrap();
+        // Notices are closed by any key
+        let notice_handler = ConditionalHandler::new(|ui_state| ui_state.notice.is_some())
+            .register_handler(KeyCode::Enter, |ui_state| ui_state.notice = None).unwrap()
+            .register_handler(KeyCode::Esc, |ui_state| ui_state.notice = None).unwrap()
+            .global_handler(|ui_state, _| ui_state.notice = None);
+        let tag_editor_handler = ConditionalHandler::new(|ui_state| ui_state.tag_editor.is_some())
+            .register_handler(KeyCode::Esc, |ui_state| ui_state.tag_editor = None).unwrap()
+            .register_handler(KeyCode::Up, |ui_state| if let Some(editor) = ui_state.tag_editor.as_mut() {
+                editor.focused = editor.focused.saturating_sub(1);
+            }).unwrap()
+            .register_handler(KeyCode::Down, |ui_state| if let Some(editor) = ui_state.tag_editor.as_mut() {
+                editor.focused = (editor.focused + 1).min(editor.fields.len() - 1);
             }).unwrap()
-        .register_handler(KeyCode::Char('n'), |ui_state| ui_state.delete = false).unwrap();
+            .register_handler(KeyCode::Backspace, |ui_state| if let Some(editor) = ui_state.tag_editor.as_mut() {
+                editor.fields[editor.focused].value.pop();
+            }).unwrap()
+            .register_handler(KeyCode::Enter, save_tags).unwrap()
+            .global_handler(|ui_state, c| if let Some(editor) = ui_state.tag_editor.as_mut() {
+                editor.fields[editor.focused].value.push(c);
+            });
         self.register_handler(main_input_handler)
+            .register_handler(tag_editor_handler)
             .register_handler(delete_handler)
+            .register_handler(notice_handler)
             .register_handler(search_handler)
     }
 }
 
+// Songs that can't be played are reported in a notice
+fn show_play_error(ui_state: &mut UiState, result: Result<(), io::Error>) {
+    if let Err(error) = result {
+        ui_state.notice = Some(format!("Playing failed: {}", error));
+    }
+}
+
+// Carries out the confirmed deletion in the background, as moving files to the trash copies
+// them when it's on another file system. The dialog stays open until finish_delete.
+fn delete(ui_state: &mut UiState) {
+    let state = ui_state.main_window_state.to_owned();
+    let mut repository = ui_state.music_repository.clone();
+    let deletion = match ui_state.delete.as_mut() {
+        Some(deletion) => deletion,
+        None => return,
+    };
+    let target = deletion.target.clone();
+    let result = Arc::new(Mutex::new(None));
+    deletion.running = Some(result.clone());
+    thread::spawn(move || {
+        let deleted = match (target, state) {
+            (DeletionTarget::Trash, _) => repository.empty_trash().map(|_| ()),
+            (DeletionTarget::Duplicates, MainWindowState::Duplicates(d, Some(i))) => repository.keep_duplicate(&d, i),
+            (DeletionTarget::Media, MainWindowState::SongFocus(s, _)) => s.delete().map(|_| repository.remove_song(s)),
+            (DeletionTarget::Media, MainWindowState::RecordFocus(r, _)) => r.delete().map(|_| repository.remove_album(r)),
+            (DeletionTarget::Media, MainWindowState::ArtistFocus(a, _)) => a.delete().map(|_| repository.remove_artist(a)),
+            _ => Ok(()),
+        };
+        *result.lock().unwrap() = Some(deleted);
+    });
+}
+
+// Updates the views once the running deletion is done, keeps the dialog open on errors
+pub(crate) fn finish_delete(ui_state: &mut UiState) {
+    let result = ui_state
+        .delete
+        .as_ref()
+        .and_then(|f| f.running.as_ref()?.lock().unwrap().take());
+    let (result, mut deletion) = match (result, ui_state.delete.take()) {
+        (Some(result), Some(deletion)) => (result, deletion),
+        (_, deletion) => {
+            ui_state.delete = deletion;
+            return;
+        }
+    };
+    deletion.running = None;
+    match (result, ui_state.main_window_state.to_owned()) {
+        (Ok(()), MainWindowState::Duplicates(mut d, Some(i))) if deletion.target == DeletionTarget::Duplicates => {
+            // The group is resolved, the other groups stay for review
+            let group = d[i].group;
+            d.retain(|f| f.group != group);
+            // The groups after it move up, so the numbers stay without gaps
+            d.iter_mut().filter(|f| f.group > group).for_each(|f| f.group -= 1);
+            let index = Some(i.min(d.len().saturating_sub(1))).filter(|_| !d.is_empty());
+            ui_state.main_window_state = MainWindowState::Duplicates(d, index);
+            ui_state.refresh_history(&HashMap::new());
+        },
+        // The deleted media can't be shown anymore, the view before it is shown instead
+        (Ok(()), _) if deletion.target == DeletionTarget::Media => leave_deleted(ui_state),
+        (Ok(()), _) => ui_state.refresh_history(&HashMap::new()),
+        // The dialog stays open to show the error. Files trashed before it are picked up by a
+        // rescan.
+        (Err(error), _) => {
+            ui_state.music_repository.rescan();
+            deletion.error = Some(error.to_string());
+            ui_state.delete = Some(deletion);
+        }
+    }
+}
+
+// Goes back to the previous view, the views are updated as the deleted songs may be listed in them
+fn leave_deleted(ui_state: &mut UiState) {
+    ui_state.refresh_history(&HashMap::new());
+    let state = ui_state.history.pop_front();
+    if state.is_none() {
+        ui_state.focus = Focus::None;
+    }
+    ui_state.main_window_state = state.unwrap_or(MainWindowState::Help);
+}
+
+// Writes the edited tags and shows the edited song or album, keeps the editor open on errors
+fn save_tags(ui_state: &mut UiState) {
+    let mut editor = match ui_state.tag_editor.take() {
+        Some(editor) => editor,
+        None => return,
+    };
+    let result = editor
+        .to_edit()
+        .and_then(|edit| ui_state.music_repository.edit_tags(&editor.songs, &edit).map_err(|e| e.to_string()));
+    if let Err(error) = result {
+        editor.error = Some(error);
+        ui_state.tag_editor = Some(editor);
+        return;
+    }
+    let song = editor.songs[0].as_ref();
+    ui_state.main_window_state = match ui_state.main_window_state.to_owned() {
+        MainWindowState::SongFocus(s, i) => MainWindowState::SongFocus(ui_state.music_repository.find_song(song).unwrap_or(s), i),
+        MainWindowState::RecordFocus(r, i) => MainWindowState::RecordFocus(ui_state.music_repository.find_album(song).unwrap_or(r), i),
+        state => state,
+    };
+}
+
 pub struct ConditionalHandler {
     handlers: HashMap<KeyCode, Box<dyn Fn(&mut UiState)>>,
     global_handler: Option<Box<dyn Fn(&mut UiState, char)>>,
@@ -171,6 +432,10 @@ impl ConditionalHandler {
         self
     }
 
+    pub fn is_active(&self, ui_state: &UiState) -> bool {
+        (*self.condition)(ui_state)
+    }
+
     pub fn handle(&self, input: KeyEvent, ui_state: &mut UiState) {
         if (*self.condition)(&ui_state.to_owned()) {
             if let KeyCode::Char(c) = input.code {
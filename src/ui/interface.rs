@@ -1,10 +1,13 @@
 use super::components::ToolbarType;
 use super::helpers;
 use super::input::Event;
-use super::input::InputHandler;
-use crate::api::fs::MusicRepository;
+use super::input::{self, InputHandler};
+use crate::api::fs::{Duplicate, DuplicateSearch, MusicRepository, PlannedMove, TagEdit};
+use crate::api::history::{ListenHistory, ScrobblerLog};
 use crate::api::player::MusicPlayer;
 use crate::api::Artist;
+use crate::api::scrobbler::{LastFm, ListenBrainz, ScrobbleService, Scrobbler};
+use crate::config::{Config, ScrobbleServiceType, ScrobblerConfig};
 use crate::api::{Album, Song};
 use crate::ui::components::EmtpyEntity;
 use crate::ui::{components, layout};
@@ -14,9 +17,12 @@ use crossterm::execute;
 use crossterm::terminal::EnterAlternateScreen;
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode, LeaveAlternateScreen};
 use itertools::Itertools;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
 use std::io;
+use std::time::Duration;
 use std::{io::Stdout, sync::mpsc::Receiver};
+use std::sync::{Arc, Mutex};
 use ratatui::style::Modifier;
 use ratatui::style::Style;
 use ratatui::text::Span;
@@ -36,14 +42,98 @@ pub(crate) struct UiState {
     pub(crate) focused_result: FocusedResult,
     pub(crate) history: VecDeque<MainWindowState>,
     pub(crate) artists: Vec<Box<dyn Artist + Send + Sync>>,
+    pub(crate) genres: Vec<String>,
+    pub(crate) decades: Vec<Option<i32>>,
+    // Version of the library the genres and decades were built from
+    pub(crate) library_version: Option<usize>,
     pub(crate) side_menu: SideMenu,
     pub(crate) focus: Focus,
-    pub(crate) delete: bool,
+    pub(crate) delete: Option<Deletion>,
+    pub(crate) tag_editor: Option<TagEditor>,
+    // A message shown in a popup until the next key is pressed
+    pub(crate) notice: Option<String>,
     pub(crate) music_player: MusicPlayer,
     pub(crate) music_repository: MusicRepository,
+    pub(crate) listen_history: ListenHistory,
+    // Whether albums and songs are listed by play count instead of their order
+    pub(crate) sort_by_plays: bool,
+    // How far back albums count as recently added
+    pub(crate) recently_added_window: Duration,
+    pub(crate) organize_template: String,
 }
 
 impl UiState {
+    // The albums of an artist in the order they are listed
+    pub fn artist_albums(&self, artist: &dyn Artist) -> Vec<Box<dyn Album + Send + Sync>> {
+        let mut albums = artist.get_albums();
+        if self.sort_by_plays {
+            self.listen_history.sort_albums(&mut albums);
+        }
+        albums
+    }
+    // Builds the views of the history again after songs were deleted or moved, so they don't
+    // list songs that are gone. Views that are empty afterwards are dropped. Moved files are
+    // looked up by their new path.
+    pub fn refresh_history(&mut self, moved: &HashMap<PathBuf, PathBuf>) {
+        let history = std::mem::take(&mut self.history);
+        self.history = history.into_iter().filter_map(|f| self.refresh_view(f, moved)).collect();
+    }
+    fn refresh_view(&self, state: MainWindowState, moved: &HashMap<PathBuf, PathBuf>) -> Option<MainWindowState> {
+        let repository = &self.music_repository;
+        let path = |song: &dyn Song| song.get_filepath().map(|f| moved.get(&f).cloned().unwrap_or(f));
+        let find_song = |song: &dyn Song| repository.find_song_at(path(song), song.get_start());
+        Some(match state {
+            MainWindowState::SongFocus(s, _) => MainWindowState::SongFocus(find_song(s.as_ref())?, None),
+            MainWindowState::RecordFocus(r, _) => {
+                let album = r.get_songs().iter().find_map(|s| repository.find_album_at(path(s.as_ref()), s.get_start()))?;
+                MainWindowState::RecordFocus(album, None)
+            },
+            MainWindowState::ArtistFocus(a, _) => {
+                let artist = repository.get_artists().into_iter().find(|f| f.get_name() == a.get_name())?;
+                MainWindowState::ArtistFocus(artist, None)
+            },
+            MainWindowState::GenreFocus(g, _, _) => {
+                let artists = repository.get_genre_artists(&g);
+                MainWindowState::GenreFocus(g, Some(artists).filter(|f| !f.is_empty())?, None)
+            },
+            MainWindowState::DecadeFocus(d, _, _) => {
+                let years = repository.get_years(d);
+                MainWindowState::DecadeFocus(d, Some(years).filter(|f| !f.is_empty())?, None)
+            },
+            MainWindowState::YearFocus(y, _, _) => {
+                let albums = repository.get_year_albums(y);
+                MainWindowState::YearFocus(y, Some(albums).filter(|f| !f.is_empty())?, None)
+            },
+            MainWindowState::RecentlyAdded(_, _) => {
+                MainWindowState::RecentlyAdded(repository.get_recently_added(self.recently_added_window), None)
+            },
+            MainWindowState::Organize(_, _) => {
+                MainWindowState::Organize(repository.plan_organize(&self.organize_template), None)
+            },
+            // Finding duplicates again takes long, so only the songs that are left are kept
+            MainWindowState::Duplicates(d, _) => {
+                let mut d = d
+                    .into_iter()
+                    .filter_map(|f| Some(Duplicate { song: find_song(f.song.as_ref())?, ..f }))
+                    .collect_vec();
+                let counts = d.iter().counts_by(|f| f.group);
+                d.retain(|f| counts[&f.group] > 1);
+                // The groups are numbered again without gaps
+                let groups = d.iter().map(|f| f.group).unique().collect_vec();
+                d.iter_mut().for_each(|f| f.group = groups.iter().position(|g| *g == f.group).unwrap());
+                MainWindowState::Duplicates(d, None)
+            },
+            state => state,
+        })
+    }
+    // The songs of an album in the order they are listed
+    pub fn album_songs(&self, album: &dyn Album) -> Vec<Box<dyn Song + Send + Sync>> {
+        let mut songs = album.get_songs();
+        if self.sort_by_plays {
+            self.listen_history.sort_songs(&mut songs);
+        }
+        songs
+    }
     pub fn scroll_down(&mut self) {
         match self.focus {
             Focus::MainWindow => match self.main_window_state.to_owned() {
@@ -67,6 +157,41 @@ impl UiState {
                         self.main_window_state = MainWindowState::RecordFocus(r, Some(0));
                     }
                 },
+                MainWindowState::GenreFocus(g, a, i) => match i {
+                    Some(i) if a.len() - i > 1 => self.main_window_state = MainWindowState::GenreFocus(g, a, Some(i+1)),
+                    None if !a.is_empty() => self.main_window_state = MainWindowState::GenreFocus(g, a, Some(0)),
+                    _ => {}
+                },
+                MainWindowState::DecadeFocus(d, y, i) => match i {
+                    Some(i) if y.len() - i > 1 => self.main_window_state = MainWindowState::DecadeFocus(d, y, Some(i+1)),
+                    None if !y.is_empty() => self.main_window_state = MainWindowState::DecadeFocus(d, y, Some(0)),
+                    _ => {}
+                },
+                MainWindowState::YearFocus(y, a, i) => match i {
+                    Some(i) if a.len() - i > 1 => self.main_window_state = MainWindowState::YearFocus(y, a, Some(i+1)),
+                    None if !a.is_empty() => self.main_window_state = MainWindowState::YearFocus(y, a, Some(0)),
+                    _ => {}
+                },
+                MainWindowState::RecentlyAdded(a, i) => match i {
+                    Some(i) if a.len() - i > 1 => self.main_window_state = MainWindowState::RecentlyAdded(a, Some(i+1)),
+                    None if !a.is_empty() => self.main_window_state = MainWindowState::RecentlyAdded(a, Some(0)),
+                    _ => {}
+                },
+                MainWindowState::Organize(m, i) => match i {
+                    Some(i) if m.len() - i > 1 => self.main_window_state = MainWindowState::Organize(m, Some(i+1)),
+                    None if !m.is_empty() => self.main_window_state = MainWindowState::Organize(m, Some(0)),
+                    _ => {}
+                },
+                MainWindowState::Duplicates(d, i) => match i {
+                    Some(i) if d.len() - i > 1 => self.main_window_state = MainWindowState::Duplicates(d, Some(i+1)),
+                    None if !d.is_empty() => self.main_window_state = MainWindowState::Duplicates(d, Some(0)),
+                    _ => {}
+                },
+                MainWindowState::SongFocus(s, i) => match i {
+                    Some(i) if s.get_chapters().len() - i > 1 => self.main_window_state = MainWindowState::SongFocus(s, Some(i+1)),
+                    None if !s.get_chapters().is_empty() => self.main_window_state = MainWindowState::SongFocus(s, Some(0)),
+                    _ => {}
+                },
                 _ => {}
             },
             Focus::SideWindow => match self.side_menu {
@@ -75,6 +200,16 @@ impl UiState {
                } else {
                     self.side_menu = SideMenu::Libary(Some(0));
                },
+               SideMenu::Genres(i) => match i {
+                   Some(i) if i + 1 < self.genres.len() => self.side_menu = SideMenu::Genres(Some(i+1)),
+                   None if !self.genres.is_empty() => self.side_menu = SideMenu::Genres(Some(0)),
+                   _ => {}
+               },
+               SideMenu::Decades(i) => match i {
+                   Some(i) if i + 1 < self.decades.len() => self.side_menu = SideMenu::Decades(Some(i+1)),
+                   None if !self.decades.is_empty() => self.side_menu = SideMenu::Decades(Some(0)),
+                   _ => {}
+               },
                _ => {}
             },
             _ => {}
@@ -103,6 +238,13 @@ impl UiState {
                         self.main_window_state = MainWindowState::RecordFocus(r, None);
                     }
                 },
+                MainWindowState::GenreFocus(g, a, Some(i)) => self.main_window_state = MainWindowState::GenreFocus(g, a, i.checked_sub(1)),
+                MainWindowState::DecadeFocus(d, y, Some(i)) => self.main_window_state = MainWindowState::DecadeFocus(d, y, i.checked_sub(1)),
+                MainWindowState::YearFocus(y, a, Some(i)) => self.main_window_state = MainWindowState::YearFocus(y, a, i.checked_sub(1)),
+                MainWindowState::RecentlyAdded(a, Some(i)) => self.main_window_state = MainWindowState::RecentlyAdded(a, i.checked_sub(1)),
+                MainWindowState::Organize(m, Some(i)) => self.main_window_state = MainWindowState::Organize(m, i.checked_sub(1)),
+                MainWindowState::Duplicates(d, Some(i)) => self.main_window_state = MainWindowState::Duplicates(d, i.checked_sub(1)),
+                MainWindowState::SongFocus(s, Some(i)) => self.main_window_state = MainWindowState::SongFocus(s, i.checked_sub(1)),
                 _ => {}
             },
             Focus::SideWindow => match self.side_menu {
@@ -110,6 +252,8 @@ impl UiState {
                     if i.unwrap() > 0 {self.side_menu = SideMenu::Libary(i.map(|v| v-1))}
                     else {self.side_menu = SideMenu::Libary(None)}
                 },
+                SideMenu::Genres(Some(i)) => self.side_menu = SideMenu::Genres(i.checked_sub(1)),
+                SideMenu::Decades(Some(i)) => self.side_menu = SideMenu::Decades(i.checked_sub(1)),
                 _ => {}
             },
             _ => {}
@@ -118,23 +262,71 @@ impl UiState {
     pub fn enter(&mut self) {
         match self.focus {
             Focus::MainWindow => match self.main_window_state.to_owned() {
+                MainWindowState::GenreFocus(_, a, Some(i)) => {
+                    self.history.push_front(self.main_window_state.to_owned());
+                    self.main_window_state = MainWindowState::ArtistFocus(a[i].to_owned(), None);
+                },
+                MainWindowState::DecadeFocus(_, y, Some(i)) => {
+                    self.history.push_front(self.main_window_state.to_owned());
+                    let albums = self.music_repository.get_year_albums(Some(y[i]));
+                    self.main_window_state = MainWindowState::YearFocus(Some(y[i]), albums, None);
+                },
+                MainWindowState::YearFocus(_, a, Some(i)) | MainWindowState::RecentlyAdded(a, Some(i)) => {
+                    self.history.push_front(self.main_window_state.to_owned());
+                    self.main_window_state = MainWindowState::RecordFocus(a[i].to_owned(), None);
+                },
+                MainWindowState::Duplicates(d, Some(i)) => {
+                    self.history.push_front(self.main_window_state.to_owned());
+                    self.main_window_state = MainWindowState::SongFocus(d[i].song.to_owned(), None);
+                },
                 MainWindowState::ArtistFocus(a, i) => if i.is_some() {
                     self.history.push_front(self.main_window_state.to_owned());
-                    self.main_window_state = MainWindowState::RecordFocus(a.get_albums().get(i.unwrap()).unwrap().to_owned(), None);
+                    self.main_window_state = MainWindowState::RecordFocus(self.artist_albums(a.as_ref()).get(i.unwrap()).unwrap().to_owned(), None);
                 },
                 MainWindowState::RecordFocus(r, i) => if i.is_some() {
                     self.history.push_front(self.main_window_state.to_owned());
-                    self.main_window_state = MainWindowState::SongFocus(r.get_songs().get(i.unwrap()).unwrap().to_owned());
+                    self.main_window_state = MainWindowState::SongFocus(self.album_songs(r.as_ref()).get(i.unwrap()).unwrap().to_owned(), None);
+                },
+                MainWindowState::SongFocus(s, Some(i)) => {
+                    let playing = self.music_player.get_song_info().is_some_and(|f| {
+                        f.name == s.get_title() && f.artist == s.get_artist_name() && f.album == s.get_album_name()
+                    });
+                    let start = s.get_chapters().get(i).unwrap().start;
+                    if !playing {
+                        if let Err(error) = self.music_player.play_song(s, true) {
+                            self.notice = Some(format!("Playing failed: {}", error));
+                            return;
+                        }
+                    }
+                    self.music_player.seek(start);
                 },
                 _ => {}
             },
-            Focus::SideWindow => {
-                if let SideMenu::Libary(i) = self.side_menu { if i.is_some() {
+            Focus::SideWindow => match self.side_menu {
+                SideMenu::Libary(Some(i)) => if let Some(artist) = self.artists.get(i).cloned() {
                     self.history.push_front(self.main_window_state.to_owned());
                     self.focus = Focus::MainWindow;
                     self.side_menu = SideMenu::Libary(None);
-                    self.main_window_state = MainWindowState::ArtistFocus(self.artists.get(i.unwrap()).unwrap().to_owned(), None);
-                }}
+                    self.main_window_state = MainWindowState::ArtistFocus(artist, None);
+                },
+                SideMenu::Genres(Some(i)) => if let Some(genre) = self.genres.get(i).cloned() {
+                    self.history.push_front(self.main_window_state.to_owned());
+                    self.focus = Focus::MainWindow;
+                    self.side_menu = SideMenu::Genres(None);
+                    let artists = self.music_repository.get_genre_artists(&genre);
+                    self.main_window_state = MainWindowState::GenreFocus(genre, artists, None);
+                },
+                SideMenu::Decades(Some(i)) => if let Some(decade) = self.decades.get(i).cloned() {
+                    self.history.push_front(self.main_window_state.to_owned());
+                    self.focus = Focus::MainWindow;
+                    self.side_menu = SideMenu::Decades(None);
+                    // Albums without a year have no years to choose from
+                    self.main_window_state = match decade {
+                        Some(decade) => MainWindowState::DecadeFocus(decade, self.music_repository.get_years(decade), None),
+                        None => MainWindowState::YearFocus(None, self.music_repository.get_year_albums(None), None),
+                    };
+                },
+                _ => {}
             },
             _ => {}
         }
@@ -145,9 +337,123 @@ impl UiState {
 pub(crate) enum MainWindowState {
     Help,
     Results((Vec<()>, Vec<()>, Vec<()>)),
-    SongFocus(Box<dyn Song + Send + Sync>),
+    SongFocus(Box<dyn Song + Send + Sync>, Option<usize>),
     ArtistFocus(Box<dyn Artist + Send + Sync>, Option<usize>),
     RecordFocus(Box<dyn Album + Send + Sync>, Option<usize>),
+    // The artists of a genre, only listing their albums of the genre
+    GenreFocus(String, Vec<Box<dyn Artist + Send + Sync>>, Option<usize>),
+    // The years of a decade
+    DecadeFocus(i32, Vec<i32>, Option<usize>),
+    // The albums of a year, None for the albums without a year
+    YearFocus(Option<i32>, Vec<Box<dyn Album + Send + Sync>>, Option<usize>),
+    // The albums added lately, the newest first
+    RecentlyAdded(Vec<Box<dyn Album + Send + Sync>>, Option<usize>),
+    // The files that organizing the library would move
+    Organize(Vec<PlannedMove>, Option<usize>),
+    // The songs that are likely in the library several times, ordered by their group
+    Duplicates(Vec<Duplicate>, Option<usize>),
+    // Duplicates that are still being looked for, replaced by Duplicates once they're found
+    FindingDuplicates(DuplicateSearch),
+}
+
+// The tags of a song or of every song of an album while they are edited
+#[derive(Clone)]
+pub(crate) struct TagEditor {
+    pub(crate) songs: Vec<Box<dyn Song + Send + Sync>>,
+    pub(crate) fields: Vec<TagField>,
+    pub(crate) focused: usize,
+    // Why the last save failed
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Clone)]
+pub(crate) struct TagField {
+    pub(crate) name: &'static str,
+    original: String,
+    pub(crate) value: String,
+}
+
+impl TagEditor {
+    pub fn for_song(song: Box<dyn Song + Send + Sync>) -> TagEditor {
+        TagEditor::new(vec![song], false)
+    }
+    // Title and track number differ between the songs, so they are left out
+    pub fn for_album(album: Box<dyn Album + Send + Sync>) -> TagEditor {
+        TagEditor::new(album.get_songs(), true)
+    }
+    fn new(songs: Vec<Box<dyn Song + Send + Sync>>, album: bool) -> TagEditor {
+        let song = songs[0].to_owned();
+        let mut fields = vec![];
+        let mut add = |name: &'static str, value: String| {
+            fields.push(TagField { name, original: value.to_owned(), value })
+        };
+        if !album {
+            add("Title", song.get_title());
+        }
+        add("Artist", song.get_artist_name());
+        add("Album", song.get_album_name());
+        add("Album artist", song.get_album_artist_name());
+        if !album {
+            add("Track", song.get_number().unwrap_or_default());
+        }
+        add("Disc", song.get_disc_number().map(|f| f.to_string()).unwrap_or_default());
+        add("Year", song.get_release_date().unwrap_or_default());
+        add("Genre", song.get_genre().unwrap_or_default());
+        TagEditor { songs, fields, focused: 0, error: None }
+    }
+    // The changed fields, numbers have to be valid. Emptied numbers remove the tag.
+    pub fn to_edit(&self) -> Result<TagEdit, String> {
+        let mut edit = TagEdit::default();
+        for field in self.fields.iter().filter(|f| f.value != f.original) {
+            let value = field.value.trim().to_string();
+            let number = || match value.as_str() {
+                "" => Ok(None),
+                value => value.parse().map(Some).map_err(|_| format!("{} has to be a number", field.name)),
+            };
+            match field.name {
+                "Title" => edit.title = Some(value),
+                "Artist" => edit.artist = Some(value),
+                "Album" => edit.album = Some(value),
+                "Album artist" => edit.album_artist = Some(value),
+                "Track" => edit.track_number = Some(number()?),
+                "Disc" => edit.disc_number = Some(number()?),
+                "Year" => edit.year = Some(number()?.map(|f: u16| f as i32)),
+                _ => edit.genre = Some(value),
+            }
+        }
+        Ok(edit)
+    }
+}
+
+// Filled with the result of a deletion once it's done
+pub(crate) type DeletionResult = Arc<Mutex<Option<Result<(), io::Error>>>>;
+
+// A deletion waiting for confirmation, with the number and size of the files it removes
+#[derive(Clone)]
+pub(crate) struct Deletion {
+    pub(crate) target: DeletionTarget,
+    pub(crate) files: usize,
+    pub(crate) bytes: u64,
+    // Why the last attempt failed
+    pub(crate) error: Option<String>,
+    // Receives the result of the confirmed deletion while it runs in the background
+    pub(crate) running: Option<DeletionResult>,
+}
+
+#[derive(Clone, PartialEq)]
+pub(crate) enum DeletionTarget {
+    // The focused song, record or artist is moved to the trash
+    Media,
+    // The deleted files are removed from the trash for good
+    Trash,
+    // The other songs of the group of the focused duplicate are moved to the trash
+    Duplicates,
+}
+
+impl Deletion {
+    pub fn new(target: DeletionTarget, (files, bytes): (usize, u64)) -> Deletion {
+        Deletion { target, files, bytes, error: None, running: None }
+    }
 }
 
 #[derive(Clone)]
@@ -163,6 +469,8 @@ pub(crate) enum FocusedResult {
 #[allow(dead_code)]
 pub(crate) enum SideMenu {
     Libary(Option<usize>),
+    Genres(Option<usize>),
+    Decades(Option<usize>),
     Queue(Option<usize>),
     None,
 }
@@ -175,7 +483,13 @@ pub enum Focus {
 }
 
 impl UiState {
-    fn new(music_player: MusicPlayer, music_repository: MusicRepository) -> UiState {
+    fn new(
+        music_player: MusicPlayer,
+        music_repository: MusicRepository,
+        listen_history: ListenHistory,
+        recently_added_window: Duration,
+        organize_template: String,
+    ) -> UiState {
         UiState {
             searching: false,
             searchbar_content: String::from(""),
@@ -184,11 +498,20 @@ impl UiState {
             focused_result: FocusedResult::None,
             history: VecDeque::new(),
             artists: vec![],
+            genres: vec![],
+            library_version: None,
+            decades: vec![],
             side_menu: SideMenu::Libary(None),
             focus: Focus::None,
-            delete: false,
+            delete: None,
+            tag_editor: None,
+            notice: None,
             music_player,
             music_repository,
+            listen_history,
+            sort_by_plays: false,
+            recently_added_window,
+            organize_template,
         }
     }
 }
@@ -221,16 +544,47 @@ pub fn restore_terminal(
 pub async fn render_interface(
     terminal: &mut Terminal<CrosstermBackend<Stdout>>,
     rx: Receiver<Event<KeyEvent>>,
+    config: Config,
 ) {
     // Init for ui state and the downloader
-    let music_player = MusicPlayer::new();
-    let music_dir = dirs::audio_dir().unwrap();
-    let mut music_repository = MusicRepository::new(music_dir);
+    let mut listen_history = ListenHistory::new().with_threshold(
+        config.history.threshold_percentage / 100.0,
+        Duration::from_secs(config.history.threshold_seconds),
+    );
+    if config.scrobbler_log.enabled {
+        let path = config.scrobbler_log.path.to_owned().or_else(|| {
+            ListenHistory::data_path()
+                .ok()
+                .map(|f| f.join(".scrobbler.log"))
+        });
+        if let Some(path) = path {
+            listen_history = listen_history.with_scrobbler_log(ScrobblerLog::new(path));
+        }
+    }
+    if config.scrobbler.enabled {
+        if let Ok(path) = ListenHistory::data_path() {
+            let scrobbler = Scrobbler::new(
+                build_scrobble_service(&config.scrobbler),
+                path.join("scrobble_queue"),
+            );
+            listen_history = listen_history.with_scrobbler(scrobbler);
+        }
+    }
+    let music_player = MusicPlayer::new(listen_history.to_owned());
+    let mut music_repository = MusicRepository::new(config.library.roots.to_owned())
+        .with_rescan_interval(Duration::from_secs(config.library.rescan_interval))
+        .with_fingerprints(config.library.fingerprints);
     
     let _ = music_repository.load_cached_artists(); 
     music_repository.watch_files();
 
-    let mut ui_state = UiState::new(music_player, music_repository);
+    let mut ui_state = UiState::new(
+        music_player,
+        music_repository,
+        listen_history,
+        Duration::from_secs(config.library.recently_added_days * 24 * 60 * 60),
+        config.library.organize_template.to_owned(),
+    );
 
     let handler = InputHandler::new().load_input_handlers();
 
@@ -248,7 +602,13 @@ pub async fn render_interface(
                 let result_layout = layout::build_search_layout(content_layout[1]);
 
                 // Main window border
-                f.render_widget(components::build_window_border(), size);
+                f.render_widget(
+                    components::build_window_border(
+                        ui_state.music_repository.get_scan_progress(),
+                        ui_state.music_repository.get_fingerprint_progress(),
+                    ),
+                    size,
+                );
 
                 // Searchbar
                 let search = if ui_state.searching {
@@ -269,7 +629,23 @@ pub async fn render_interface(
                                 .map(|f| f.get_name())
                                 .collect_vec(),
                             i,
-                            content_layout[0].height as usize - 3,
+                            content_layout[0].height.saturating_sub(3) as usize,
+                        ),
+                        content_layout[0],
+                    ),
+                    SideMenu::Genres(i) => f.render_widget(
+                        components::build_genre_list(
+                            ui_state.genres.to_owned(),
+                            i,
+                            content_layout[0].height.saturating_sub(3) as usize,
+                        ),
+                        content_layout[0],
+                    ),
+                    SideMenu::Decades(i) => f.render_widget(
+                        components::build_decade_list(
+                            ui_state.decades.to_owned(),
+                            i,
+                            content_layout[0].height.saturating_sub(3) as usize,
                         ),
                         content_layout[0],
                     ),
@@ -277,7 +653,7 @@ pub async fn render_interface(
                         components::build_queue(
                             ui_state.music_player.get_queue(),
                             i,
-                            content_layout[0].height as usize - 3,
+                            content_layout[0].height.saturating_sub(3) as usize,
                         ),
                         content_layout[0],
                     ),
@@ -291,11 +667,35 @@ pub async fn render_interface(
                         f.render_widget(components::build_help_window(), content_layout[1])
                     }
                     // The window for viewing details to a song
-                    MainWindowState::SongFocus(s) => {
-                        f.render_widget(
-                            components::build_song_focus(s.to_owned()),
-                            content_layout[1],
-                        );
+                    MainWindowState::SongFocus(s, index) => {
+                        if s.get_chapters().is_empty() {
+                            f.render_widget(
+                                components::build_song_focus(
+                                    s.to_owned(),
+                                    ui_state.listen_history.get_stats(s.as_ref()),
+                                ),
+                                content_layout[1],
+                            );
+                        } else {
+                            let song_focus_layout =
+                                layout::build_song_focus_layout().split(content_layout[1]);
+                            f.render_widget(
+                                components::build_song_focus(
+                                    s.to_owned(),
+                                    ui_state.listen_history.get_stats(s.as_ref()),
+                                ),
+                                song_focus_layout[0],
+                            );
+                            f.render_widget(
+                                components::build_chapter_list(
+                                    s.to_owned(),
+                                    index,
+                                    song_focus_layout[1].height.saturating_sub(3) as usize,
+                                    &current_song,
+                                ),
+                                song_focus_layout[1],
+                            );
+                        }
                         if s.is_local() {
                             f.render_widget(
                                 components::build_focus_toolbox(ToolbarType::Play),
@@ -313,9 +713,11 @@ pub async fn render_interface(
                         f.render_widget(
                             components::build_record_focus(
                                 r.to_owned(),
+                                ui_state.album_songs(r.as_ref()),
                                 index,
-                                content_layout[1].height as usize - 3,
+                                content_layout[1].height.saturating_sub(3) as usize,
                                 &current_song,
+                                &ui_state.listen_history,
                             ),
                             content_layout[1],
                         );
@@ -335,9 +737,10 @@ pub async fn render_interface(
                     MainWindowState::ArtistFocus(a, index) => {
                         f.render_widget(
                             components::build_artist_focus(
-                                a,
+                                a.get_name(),
+                                ui_state.artist_albums(a.as_ref()),
                                 index,
-                                content_layout[1].height as usize - 3,
+                                content_layout[1].height.saturating_sub(3) as usize,
                             ),
                             content_layout[1],
                         );
@@ -346,6 +749,109 @@ pub async fn render_interface(
                             focus_layout[1],
                         );
                     }
+                    // The window for browsing the artists of a genre
+                    MainWindowState::GenreFocus(g, a, index) => {
+                        f.render_widget(
+                            components::build_genre_focus(
+                                g,
+                                a,
+                                index,
+                                content_layout[1].height.saturating_sub(3) as usize,
+                            ),
+                            content_layout[1],
+                        );
+                        f.render_widget(
+                            components::build_focus_toolbox(ToolbarType::Browse),
+                            focus_layout[1],
+                        );
+                    }
+                    // The window for choosing a year of a decade
+                    MainWindowState::DecadeFocus(d, y, index) => {
+                        f.render_widget(
+                            components::build_decade_focus(
+                                d,
+                                y,
+                                index,
+                                content_layout[1].height.saturating_sub(3) as usize,
+                            ),
+                            content_layout[1],
+                        );
+                        f.render_widget(
+                            components::build_focus_toolbox(ToolbarType::Browse),
+                            focus_layout[1],
+                        );
+                    }
+                    // The window for the albums of a year, which can be played together
+                    MainWindowState::YearFocus(y, a, index) => {
+                        f.render_widget(
+                            components::build_year_focus(
+                                y,
+                                a,
+                                index,
+                                content_layout[1].height.saturating_sub(3) as usize,
+                            ),
+                            content_layout[1],
+                        );
+                        f.render_widget(
+                            components::build_focus_toolbox(ToolbarType::PlayAll),
+                            focus_layout[1],
+                        );
+                    }
+                    // The window for the albums added lately
+                    MainWindowState::RecentlyAdded(a, index) => {
+                        f.render_widget(
+                            components::build_recently_added(
+                                a,
+                                index,
+                                content_layout[1].height.saturating_sub(3) as usize,
+                            ),
+                            content_layout[1],
+                        );
+                        f.render_widget(
+                            components::build_focus_toolbox(ToolbarType::Browse),
+                            focus_layout[1],
+                        );
+                    }
+                    // The preview of organizing the library
+                    MainWindowState::Organize(m, index) => {
+                        f.render_widget(
+                            components::build_organize_preview(
+                                m,
+                                index,
+                                content_layout[1].height.saturating_sub(3) as usize,
+                            ),
+                            content_layout[1],
+                        );
+                        f.render_widget(
+                            components::build_focus_toolbox(ToolbarType::Organize),
+                            focus_layout[1],
+                        );
+                    }
+                    MainWindowState::FindingDuplicates(search) => {
+                        f.render_widget(
+                            components::build_duplicate_search(search.get_progress()),
+                            content_layout[1],
+                        );
+                        f.render_widget(
+                            components::build_focus_toolbox(ToolbarType::Browse),
+                            focus_layout[1],
+                        );
+                    }
+                    // Songs that are in the library several times
+                    MainWindowState::Duplicates(d, index) => {
+                        f.render_widget(
+                            components::build_duplicates(
+                                d,
+                                index,
+                                content_layout[1].height.saturating_sub(3) as usize,
+                            ),
+                            content_layout[1],
+                        );
+                        f.render_widget(
+                            components::build_focus_toolbox(ToolbarType::Duplicates),
+                            focus_layout[1],
+                        );
+                    }
                     MainWindowState::Results(_t) => {
                         // Determines which of the search results is focused
                         let scroll_value = match ui_state.focused_result {
@@ -356,7 +862,7 @@ pub async fn render_interface(
                             _ => (None, None, None, None),
                         };
                         // Calculates how many results can be rendered on the current screen
-                        let displayable_results = result_layout[0].height as usize - 3;
+                        let displayable_results = result_layout[0].height.saturating_sub(3) as usize;
                         // Song search results
                         f.render_widget(
                             components::build_result_box(
@@ -412,17 +918,68 @@ pub async fn render_interface(
                     )
                 }
                 ui_state.artists = ui_state.music_repository.get_artists();
-                if ui_state.delete {
-                    let text = vec![
-                        Line::from(vec![Span::raw(
-                            "Are you sure that you want to delete that?",
-                        )]),
-                        Line::from(vec![]),
-                        Line::from(vec![
+                let library_version = ui_state.music_repository.get_version();
+                if ui_state.library_version != Some(library_version) {
+                    ui_state.genres = ui_state.music_repository.get_genres();
+                    ui_state.decades = ui_state.music_repository.get_decades();
+                    ui_state.library_version = Some(library_version);
+                    // Genres and decades may have disappeared from below the selection
+                    if let SideMenu::Genres(Some(i)) = ui_state.side_menu {
+                        let genres = &ui_state.genres;
+                        let index = Some(i.min(genres.len().saturating_sub(1))).filter(|_| !genres.is_empty());
+                        ui_state.side_menu = SideMenu::Genres(index);
+                    }
+                    if let SideMenu::Decades(Some(i)) = ui_state.side_menu {
+                        let decades = &ui_state.decades;
+                        let index = Some(i.min(decades.len().saturating_sub(1))).filter(|_| !decades.is_empty());
+                        ui_state.side_menu = SideMenu::Decades(index);
+                    }
+                }
+                input::finish_delete(&mut ui_state);
+                if let MainWindowState::FindingDuplicates(search) = &ui_state.main_window_state {
+                    if let Some(duplicates) = search.get_result() {
+                        ui_state.main_window_state = MainWindowState::Duplicates(duplicates, None);
+                    }
+                }
+                if let Some(editor) = &ui_state.tag_editor {
+                    let area = helpers::centered_rect(60, 50, size);
+                    f.render_widget(Clear, area);
+                    f.render_widget(components::build_tag_editor(editor), area);
+                }
+                if let Some(deletion) = &ui_state.delete {
+                    let files = match deletion.files {
+                        1 => "1 file".to_string(),
+                        n => format!("{} files", n),
+                    };
+                    let bytes = helpers::format_size(deletion.bytes);
+                    let question = match deletion.target {
+                        DeletionTarget::Media => format!("Move {} ({}) to the trash?", files, bytes),
+                        DeletionTarget::Duplicates => {
+                            format!("Keep this song and move {} ({}) to the trash?", files, bytes)
+                        }
+                        DeletionTarget::Trash => {
+                            format!("Permanently remove {} ({}) from the trash?", files, bytes)
+                        }
+                    };
+                    let answers = match deletion.running {
+                        Some(_) if deletion.target == DeletionTarget::Trash => {
+                            vec![Span::raw("Removing files...")]
+                        }
+                        Some(_) => vec![Span::raw("Moving files to the trash...")],
+                        None => vec![
                             Span::styled("[y]es", Style::default().add_modifier(Modifier::BOLD)),
                             Span::styled(" [n]o", Style::default().add_modifier(Modifier::BOLD)),
-                        ]),
+                        ],
+                    };
+                    let mut text = vec![
+                        Line::from(vec![Span::raw(question)]),
+                        Line::from(vec![]),
+                        Line::from(answers),
                     ];
+                    if let Some(error) = &deletion.error {
+                        text.push(Line::from(vec![]));
+                        text.push(Line::from(vec![Span::raw(error.to_owned())]));
+                    }
                     let block = Paragraph::new(text).block(
                         Block::default()
                             .title("Delete Confirmation")
@@ -432,11 +989,40 @@ pub async fn render_interface(
                     f.render_widget(Clear, area);
                     f.render_widget(block, area);
                 }
+                if let Some(notice) = &ui_state.notice {
+                    let block = Paragraph::new(vec![Line::from(vec![Span::raw(notice.to_owned())])])
+                        .block(Block::default().title("Notice").borders(Borders::all()));
+                    let area = helpers::centered_rect(60, 20, size);
+                    f.render_widget(Clear, area);
+                    f.render_widget(block, area);
+                }
             })
             .unwrap();
 
         // Handles keyboard input
         if let Event::Input(event) = rx.recv().unwrap() { handler.handle(event, &mut ui_state) }
     }
+    ui_state.music_player.finish();
     let _ = ui_state.music_repository.cache_artists();
 }
+
+fn build_scrobble_service(config: &ScrobblerConfig) -> Box<dyn ScrobbleService> {
+    match config.service {
+        ScrobbleServiceType::ListenBrainz => Box::new(ListenBrainz::new(
+            config
+                .url
+                .to_owned()
+                .unwrap_or("https://api.listenbrainz.org".to_string()),
+            config.token.to_owned(),
+        )),
+        ScrobbleServiceType::LastFm => Box::new(LastFm::new(
+            config
+                .url
+                .to_owned()
+                .unwrap_or("https://ws.audioscrobbler.com/2.0/".to_string()),
+            config.api_key.to_owned(),
+            config.api_secret.to_owned(),
+            config.session_key.to_owned(),
+        )),
+    }
+}
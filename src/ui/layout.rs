@@ -59,6 +59,13 @@ pub fn build_focus_layout() -> Layout {
         .constraints([Constraint::Percentage(99), Constraint::Percentage(1)].as_ref())
 }
 
+pub fn build_song_focus_layout() -> Layout {
+    Layout::default()
+        .direction(Direction::Vertical)
+        .margin(0)
+        .constraints([Constraint::Length(4), Constraint::Min(3)].as_ref())
+}
+
 pub fn build_play_layout() -> Layout {
     Layout::default()
         .direction(Direction::Vertical)